@@ -0,0 +1,108 @@
+use std::{future::Future, io::Error, sync::Arc};
+
+use arc_swap::ArcSwapOption;
+use criterion::{criterion_group, criterion_main, Criterion};
+use tokio::{runtime::Builder, sync::RwLock, task::JoinSet};
+use tourniquet::{async_trait, Connector, RoundRobin};
+
+const TASKS: usize = 8;
+const CALLS: usize = 1000;
+
+struct Conn;
+
+#[async_trait]
+impl Connector<u16, u16, Error> for Conn {
+    async fn connect(&self, src: &u16) -> Result<u16, Error> {
+        Ok(*src)
+    }
+}
+
+/// Calls against an already established connection, which is the hot path.
+fn run(c: &mut Criterion) {
+    let rt = Builder::new_current_thread().build().unwrap();
+    let rr = RoundRobin::new(vec![1, 2, 3], Conn);
+
+    c.bench_function("run", |b| {
+        b.to_async(&rt).iter(|| rr.run(|svc| async move { Ok(*svc) }));
+    });
+}
+
+/// Concurrent calls from several threads, against an already established connection.
+fn run_concurrent(c: &mut Criterion) {
+    let rt = Builder::new_multi_thread().worker_threads(TASKS).build().unwrap();
+    let rr = Arc::new(RoundRobin::new(vec![1, 2, 3], Conn));
+
+    c.bench_function("run_concurrent", |b| {
+        b.to_async(&rt).iter(|| async {
+            let mut tasks = JoinSet::new();
+            for _ in 0..TASKS {
+                let rr = rr.clone();
+                tasks.spawn(async move {
+                    for _ in 0..CALLS {
+                        rr.run(|svc| async move { Ok(*svc) }).await.unwrap();
+                    }
+                });
+            }
+            tasks.join_all().await;
+        });
+    });
+}
+
+/// Connection held by the slot, as the round-robin's one: the service and its source.
+#[derive(Clone)]
+struct Connection {
+    svc: Arc<u16>,
+    _source: Arc<u16>,
+}
+
+/// Run `call` `CALLS` times from each of `TASKS` tasks.
+async fn concurrently<F, Fut>(call: F)
+where
+    F: Fn() -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = u16> + Send,
+{
+    let mut tasks = JoinSet::new();
+    for _ in 0..TASKS {
+        let call = call.clone();
+        tasks.spawn(async move {
+            for _ in 0..CALLS {
+                std::hint::black_box(call().await);
+            }
+        });
+    }
+    tasks.join_all().await;
+}
+
+/// Reading an established connection from the slot, as done by each call: the tokio `RwLock` slot
+/// used before, against the atomically swapped one used now.
+fn slot(c: &mut Criterion) {
+    let conn = Connection { svc: Arc::new(1), _source: Arc::new(1) };
+    let rwlock = Arc::new(RwLock::new(Some(conn.clone())));
+    let arc_swap = Arc::new(ArcSwapOption::from_pointee(conn));
+
+    let read_rwlock = move || {
+        let rwlock = rwlock.clone();
+        async move { *rwlock.read().await.clone().unwrap().svc }
+    };
+    let read_arc_swap = move || {
+        let arc_swap = arc_swap.clone();
+        async move { *arc_swap.load_full().unwrap().svc }
+    };
+
+    let rt = Builder::new_current_thread().build().unwrap();
+    let mut group = c.benchmark_group("slot");
+    group.bench_function("rwlock", |b| b.to_async(&rt).iter(&read_rwlock));
+    group.bench_function("arc_swap", |b| b.to_async(&rt).iter(&read_arc_swap));
+    group.finish();
+
+    let rt = Builder::new_multi_thread().worker_threads(TASKS).build().unwrap();
+    let mut group = c.benchmark_group("slot_concurrent");
+    group.bench_function("rwlock", |b| b.to_async(&rt).iter(|| concurrently(read_rwlock.clone())));
+    group.bench_function("arc_swap", |b| {
+        b.to_async(&rt).iter(|| concurrently(read_arc_swap.clone()))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, run, run_concurrent, slot);
+criterion_main!(benches);
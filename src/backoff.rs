@@ -0,0 +1,151 @@
+//! Backoff policies applied between failover attempts.
+//!
+//! By default, [`RoundRobin`](crate::RoundRobin) immediately tries the next service after a next
+//! error. When a whole set of services blips at once, this burns through all attempts in a few
+//! milliseconds. A [`Backoff`] policy introduces a delay between attempts, giving the services
+//! some time to recover.
+
+use std::time::Duration;
+
+/// Policy computing how long to wait before retrying.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// use tourniquet::backoff::{Backoff, Exponential};
+///
+/// let backoff = Exponential::new(Duration::from_millis(10), 2.0).capped(Duration::from_secs(1));
+///
+/// assert_eq!(backoff.delay(1, Duration::ZERO), Duration::from_millis(10));
+/// assert_eq!(backoff.delay(3, Duration::from_millis(20)), Duration::from_millis(40));
+/// assert_eq!(backoff.delay(20, Duration::from_secs(1)), Duration::from_secs(1));
+/// ```
+pub trait Backoff: Send + Sync {
+    /// Delay to wait before the `retry`-th retry (starting at 1), given the previously applied
+    /// delay (zero for the first retry).
+    fn delay(&self, retry: u32, previous: Duration) -> Duration;
+
+    /// Cap the delays returned by this policy to `max`.
+    fn capped(self, max: Duration) -> Capped<Self>
+    where
+        Self: Sized,
+    {
+        Capped { inner: self, max }
+    }
+}
+
+/// Do not wait at all, and retry immediately. This is the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoBackoff;
+
+impl Backoff for NoBackoff {
+    fn delay(&self, _retry: u32, _previous: Duration) -> Duration {
+        Duration::ZERO
+    }
+}
+
+/// Always wait the same amount of time.
+#[derive(Clone, Copy, Debug)]
+pub struct Constant(pub Duration);
+
+impl Backoff for Constant {
+    fn delay(&self, _retry: u32, _previous: Duration) -> Duration {
+        self.0
+    }
+}
+
+/// Wait `base * factor ^ (retry - 1)`.
+#[derive(Clone, Copy, Debug)]
+pub struct Exponential {
+    base: Duration,
+    factor: f64,
+}
+
+impl Exponential {
+    pub fn new(base: Duration, factor: f64) -> Self {
+        Self { base, factor }
+    }
+}
+
+impl Backoff for Exponential {
+    fn delay(&self, retry: u32, _previous: Duration) -> Duration {
+        let exp = retry.saturating_sub(1).min(i32::MAX as u32) as i32;
+        Duration::try_from_secs_f64(self.base.as_secs_f64() * self.factor.powi(exp))
+            .unwrap_or(Duration::MAX)
+    }
+}
+
+/// "Decorrelated jitter" as described in the
+/// [AWS architecture blog](https://aws.amazon.com/blogs/architecture/exponential-backoff-and-jitter/).
+///
+/// Each delay is picked at random between `base` and three times the previous delay, capped to
+/// `max`. This spreads retries of concurrent callers over time, while still growing roughly
+/// exponentially.
+#[derive(Clone, Copy, Debug)]
+pub struct DecorrelatedJitter {
+    base: Duration,
+    max: Duration,
+}
+
+impl DecorrelatedJitter {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self { base, max }
+    }
+}
+
+impl Backoff for DecorrelatedJitter {
+    fn delay(&self, _retry: u32, previous: Duration) -> Duration {
+        let low = self.base.as_nanos().min(u64::MAX as u128) as u64;
+        let high = previous.saturating_mul(3).as_nanos().min(u64::MAX as u128) as u64;
+        let delay = if high > low { fastrand::u64(low..=high) } else { low };
+        Duration::from_nanos(delay).min(self.max)
+    }
+}
+
+/// Caps the delay of another policy. Usually built with [`Backoff::capped`].
+#[derive(Clone, Copy, Debug)]
+pub struct Capped<B> {
+    inner: B,
+    max: Duration,
+}
+
+impl<B: Backoff> Backoff for Capped<B> {
+    fn delay(&self, retry: u32, previous: Duration) -> Duration {
+        self.inner.delay(retry, previous).min(self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn test_exponential() {
+        let backoff = Exponential::new(10 * MS, 2.0);
+
+        assert_eq!(backoff.delay(1, Duration::ZERO), 10 * MS);
+        assert_eq!(backoff.delay(2, 10 * MS), 20 * MS);
+        assert_eq!(backoff.delay(5, 80 * MS), 160 * MS);
+        assert_eq!(backoff.delay(u32::MAX, Duration::ZERO), Duration::MAX);
+        assert_eq!(backoff.capped(100 * MS).delay(5, 80 * MS), 100 * MS);
+    }
+
+    #[test]
+    fn test_decorrelated_jitter() {
+        let backoff = DecorrelatedJitter::new(10 * MS, 200 * MS);
+
+        assert_eq!(backoff.delay(1, Duration::ZERO), 10 * MS);
+
+        let mut previous = Duration::ZERO;
+        for retry in 1..100 {
+            let delay = backoff.delay(retry, previous);
+            assert!(delay >= 10 * MS, "{:?} is below base", delay);
+            assert!(delay <= (previous * 3).max(10 * MS), "{:?} grew too fast", delay);
+            assert!(delay <= 200 * MS, "{:?} is above cap", delay);
+            previous = delay;
+        }
+    }
+}
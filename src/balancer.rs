@@ -0,0 +1,507 @@
+//! Load balancing between services.
+//!
+//! Where [`RoundRobin`](crate::RoundRobin) sticks to a single service for as long as it is up, a
+//! [`Balancer`] keeps a connection to every service, and spreads calls across them according to
+//! a [`Strategy`]. It still fails over to another service on next errors, and honours the other
+//! [`Classification`]s the same way.
+
+use std::{
+    fmt::{Debug, Display},
+    future::Future,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{
+    breaker::{CircuitBreaker, CircuitState},
+    health,
+    logging::Logging,
+    redact::{redact_passwords, Redact},
+    slot::{Acquired, Slot},
+    sources::{Source, Sources},
+    task::Task,
+    Classification, Classify, Connector, Error, Failure, HealthCheck, RunError,
+};
+
+/// How a [`Balancer`] picks the service of a call.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// Use each service in turn. This is the default.
+    #[default]
+    RoundRobin,
+    /// Pick a service at random.
+    Random,
+    /// Pick the service with the fewest calls in flight.
+    LeastInFlight,
+}
+
+/// Counts a call in flight for as long as it lives.
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl<'a> InFlight<'a> {
+    fn new(count: &'a AtomicUsize) -> Self {
+        count.fetch_add(1, Ordering::Relaxed);
+        Self(count)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Load balancing manager.
+///
+/// This holds a list of services, a way to connect to said services, and spreads calls across
+/// the connected services. Connections are lazily established, once for all concurrent calls as
+/// with [`RoundRobin`](crate::RoundRobin), and dropped on next errors.
+///
+/// Unhealthy services, as reported by the [`health_check`](Self::health_check), and services with
+/// an open circuit, are left out.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::io::Error;
+/// # use tourniquet::{async_trait, Connector};
+/// use tourniquet::balancer::{Balancer, Strategy};
+/// #
+/// # struct Conn;
+/// #
+/// # #[async_trait]
+/// # impl Connector<u16, u16, Error> for Conn {
+/// #     async fn connect(&self, src: &u16) -> Result<u16, Error> {
+/// #         Ok(*src)
+/// #     }
+/// # }
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let lb = Balancer::new(vec![1, 2, 3], Conn).strategy(Strategy::LeastInFlight);
+///
+/// let n = lb.run(|svc| async move { Ok(*svc) }).await.unwrap();
+/// # }
+/// ```
+pub struct Balancer<SvcSrc, Svc, E, Conn>
+where
+    Conn: Connector<SvcSrc, Svc, E>,
+{
+    /// Sources used to connect to the services.
+    sources: Arc<Sources<SvcSrc>>,
+
+    /// Connection to each service, in the same order as `sources`.
+    slots: Vec<Slot<Svc, SvcSrc, E>>,
+
+    /// Calls in flight against each service, in the same order as `sources`.
+    in_flight: Vec<AtomicUsize>,
+
+    /// Async connection handler.
+    connector: Conn,
+
+    /// How services are picked.
+    strategy: Strategy,
+
+    /// How many services to try before giving up. Defaults to the service count, plus one.
+    max_attempts: Option<usize>,
+
+    /// Circuit breaker configuration, if enabled.
+    circuit_breaker: Option<CircuitBreaker>,
+
+    /// Logging configuration.
+    logging: Logging,
+
+    /// Representation of the sources in logs.
+    redact: Redact<SvcSrc>,
+
+    /// Background health checking task, if enabled.
+    health_task: Option<Task>,
+
+    /// Ever increasing counter, used as a starting point when picking a service.
+    next: AtomicUsize,
+
+    _phantom: PhantomData<E>,
+}
+
+impl<SvcSrc, Svc, E, Conn> Balancer<SvcSrc, Svc, E, Conn>
+where
+    SvcSrc: Debug,
+    E: Classify + Display,
+    Conn: Connector<SvcSrc, Svc, E>,
+{
+    /// Build a new load balancing manager, see [`RoundRobin::new`](crate::RoundRobin::new).
+    pub fn new(sources: Vec<SvcSrc>, connector: Conn) -> Self {
+        Self {
+            slots: sources.iter().map(|_| Slot::new()).collect(),
+            in_flight: sources.iter().map(|_| AtomicUsize::new(0)).collect(),
+            sources: Arc::new(Sources::new(sources)),
+            connector,
+            strategy: Strategy::default(),
+            max_attempts: None,
+            circuit_breaker: None,
+            logging: Logging::default(),
+            redact: redact_passwords,
+            health_task: None,
+            next: AtomicUsize::new(0),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Set how services are picked.
+    pub fn set_strategy(&mut self, strategy: Strategy) {
+        self.strategy = strategy;
+    }
+
+    /// Set how services are picked.
+    pub fn strategy(self, strategy: Strategy) -> Self {
+        Self { strategy, ..self }
+    }
+
+    /// Set how many times we will try another service in case of failure.
+    pub fn set_max_attempts(&mut self, count: usize) {
+        self.max_attempts = Some(count);
+    }
+
+    /// Set how many times we will try another service in case of failure.
+    pub fn max_attempts(self, count: usize) -> Self {
+        Self { max_attempts: Some(count), ..self }
+    }
+
+    /// Enable per-service circuit breakers, see
+    /// [`RoundRobin::circuit_breaker`](crate::RoundRobin::circuit_breaker).
+    pub fn set_circuit_breaker(&mut self, breaker: CircuitBreaker) {
+        self.circuit_breaker = Some(breaker);
+    }
+
+    /// Enable per-service circuit breakers, see
+    /// [`RoundRobin::circuit_breaker`](crate::RoundRobin::circuit_breaker).
+    pub fn circuit_breaker(self, breaker: CircuitBreaker) -> Self {
+        Self { circuit_breaker: Some(breaker), ..self }
+    }
+
+    /// Set how failed attempts are logged, see [`Logging`]. Failovers and exhausted calls are not
+    /// logged by load balancers.
+    pub fn set_logging(&mut self, logging: Logging) {
+        self.logging = logging;
+        self.sources.set_logging(logging, self.redact);
+    }
+
+    /// Set how failed attempts are logged, see [`Logging`]. Failovers and exhausted calls are not
+    /// logged by load balancers.
+    pub fn logging(mut self, logging: Logging) -> Self {
+        self.set_logging(logging);
+        self
+    }
+
+    /// Set how sources are represented in logs, see
+    /// [`RoundRobin::redact`](crate::RoundRobin::redact).
+    pub fn set_redact(&mut self, redact: Redact<SvcSrc>) {
+        self.redact = redact;
+        self.sources.set_logging(self.logging, redact);
+    }
+
+    /// Set how sources are represented in logs, see
+    /// [`RoundRobin::redact`](crate::RoundRobin::redact).
+    pub fn redact(mut self, redact: Redact<SvcSrc>) -> Self {
+        self.set_redact(redact);
+        self
+    }
+
+    /// Circuit state of the service at `index` in the source list, or `None` if there is no such
+    /// service.
+    pub fn circuit_state(&self, index: usize) -> Option<CircuitState> {
+        self.sources.snapshot().get(index).map(|s| s.breaker.state())
+    }
+
+    /// Start probing all services every `period` in the background, see
+    /// [`RoundRobin::health_check`](crate::RoundRobin::health_check).
+    ///
+    /// # Panics
+    ///
+    /// This must be called from within a Tokio runtime, and panics if `period` is zero.
+    pub fn set_health_check<H>(&mut self, check: H, period: Duration)
+    where
+        SvcSrc: Send + Sync + 'static,
+        H: HealthCheck<SvcSrc> + Send + Sync + 'static,
+    {
+        self.health_task = Some(health::spawn(self.sources.clone(), check, period));
+    }
+
+    /// Start probing all services every `period` in the background, see
+    /// [`RoundRobin::health_check`](crate::RoundRobin::health_check).
+    ///
+    /// # Panics
+    ///
+    /// This must be called from within a Tokio runtime, and panics if `period` is zero.
+    pub fn health_check<H>(mut self, check: H, period: Duration) -> Self
+    where
+        SvcSrc: Send + Sync + 'static,
+        H: HealthCheck<SvcSrc> + Send + Sync + 'static,
+    {
+        self.set_health_check(check, period);
+        self
+    }
+
+    /// Number of calls in flight against the service at `index` in the source list, or `None` if
+    /// there is no such service.
+    pub fn in_flight(&self, index: usize) -> Option<usize> {
+        self.in_flight.get(index).map(|n| n.load(Ordering::Relaxed))
+    }
+
+    /// Pick a service among the candidates, according to the strategy.
+    fn pick(&self, candidates: &[usize]) -> Option<usize> {
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let rotated =
+            || candidates.iter().cycle().skip(start % candidates.len()).take(candidates.len());
+        match self.strategy {
+            Strategy::RoundRobin => rotated().next().copied(),
+            Strategy::Random => Some(candidates[fastrand::usize(..candidates.len())]),
+            Strategy::LeastInFlight => {
+                rotated().min_by_key(|&&i| self.in_flight[i].load(Ordering::Relaxed)).copied()
+            }
+        }
+    }
+
+    async fn run_inner<Run, RunFut, T>(
+        &self,
+        run: &Run,
+        index: usize,
+        source: &Arc<Source<SvcSrc>>,
+    ) -> Result<T, Failure<E>>
+    where
+        Run: Fn(Arc<Svc>) -> RunFut,
+        RunFut: Future<Output = Result<T, E>>,
+    {
+        let breaker = self.circuit_breaker.as_ref();
+        let slot = &self.slots[index];
+        let _in_flight = InFlight::new(&self.in_flight[index]);
+
+        // Connect if not already connected. Concurrent callers wait for the connection in
+        // progress, if any, rather than connecting on their own.
+        let conn = match slot.acquire().await {
+            Acquired::Connected(conn) => conn,
+            Acquired::Failed(f) => return Err(f),
+            Acquired::Connect(mut connecting) => {
+                if let Err(e) = source.admit(breaker) {
+                    return Err(Failure::skipped(e));
+                }
+
+                let res = self.connector.connect(&source.src).await.map_err(Failure::from);
+                source.report(breaker, res.as_ref().is_err_and(Failure::is_next));
+                connecting.complete(index, source, res)?
+            }
+        };
+
+        let res = run(conn.svc.clone()).await.map_err(Failure::from);
+        source.report(breaker, res.as_ref().is_err_and(Failure::is_next));
+
+        // Trash handler only if it was not already replaced
+        let class = res.as_ref().err().map(|f| f.class);
+        if matches!(class, Some(Classification::Next(_) | Classification::Reconnect(_))) {
+            slot.disconnect(&conn);
+        }
+
+        res
+    }
+
+    /// Run the provided async function against a service picked according to the strategy,
+    /// trying other services on next errors.
+    ///
+    /// The connection to the service will be established at this point if not already established.
+    /// Tourniquet's own errors, like [`Error::NoSources`] should the source list be empty, are
+    /// converted into `E`.
+    pub async fn run<R, Fut, T>(&self, run: R) -> Result<T, E>
+    where
+        E: From<Error> + Send + Sync + 'static,
+        R: Fn(Arc<Svc>) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        self.run_attempts(run).await.map_err(RunError::into_error)
+    }
+
+    /// Run the provided async function, trying services until it succeeds.
+    async fn run_attempts<R, Fut, T>(&self, run: R) -> Result<T, RunError<E>>
+    where
+        R: Fn(Arc<Svc>) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let sources = self.sources.snapshot();
+        let n_svc = sources.len();
+        if n_svc == 0 {
+            return Err(Error::NoSources.into());
+        }
+        let max_attempts = self.max_attempts.unwrap_or(n_svc + 1);
+        let mut tried = vec![false; n_svc];
+        let (mut attempts, mut skipped_svc, mut last_error) = (0usize, 0usize, None);
+        let mut retry = None;
+
+        loop {
+            let healthy: Vec<_> =
+                (0..n_svc).filter(|&i| sources[i].healthy.load(Ordering::Relaxed)).collect();
+
+            // Once all services were tried, start over
+            if healthy.iter().all(|&i| tried[i]) {
+                tried.fill(false);
+            }
+
+            let candidates: Vec<_> = healthy.iter().copied().filter(|&i| !tried[i]).collect();
+            let index = match retry.take().or_else(|| self.pick(&candidates)) {
+                Some(index) => index,
+                None => return Err(last_error.unwrap_or_else(|| Error::Unhealthy.into())),
+            };
+            tried[index] = true;
+
+            match self.run_inner(&run, index, &sources[index]).await {
+                Ok(t) => return Ok(t),
+                Err(Failure { error: e, skipped: true, .. }) => {
+                    // The circuit is open: try another service without it counting as an
+                    // attempt, unless all circuits are open.
+                    skipped_svc += 1;
+                    if skipped_svc >= healthy.len() {
+                        return Err(last_error.unwrap_or(e));
+                    }
+                }
+                Err(Failure { error: e, class, .. }) => {
+                    skipped_svc = 0;
+
+                    if class == Classification::Fatal {
+                        return Err(e);
+                    }
+
+                    self.logging.log_failure(index, &sources[index], self.redact, &e);
+
+                    attempts += 1;
+                    if attempts >= max_attempts {
+                        return Err(e);
+                    }
+
+                    // Retries and reconnections stick to the same service
+                    if !matches!(class, Classification::Next(_)) {
+                        retry = Some(index);
+                    }
+                    if let Some(after) = class.retry_after() {
+                        tokio::time::sleep(after).await;
+                    }
+                    last_error = Some(e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::async_trait;
+    use std::io::{Error as IoError, ErrorKind};
+
+    /// Connector failing for sources below `ok_from`, yielding first so that concurrent calls
+    /// overlap
+    struct Conn {
+        count: Arc<AtomicUsize>,
+        ok_from: usize,
+    }
+
+    #[async_trait]
+    impl Connector<usize, usize, IoError> for Conn {
+        async fn connect(&self, src: &usize) -> Result<usize, IoError> {
+            self.count.fetch_add(1, Ordering::Relaxed);
+            tokio::task::yield_now().await;
+            if *src < self.ok_from {
+                Err(ErrorKind::ConnectionRefused.into())
+            } else {
+                Ok(*src)
+            }
+        }
+    }
+
+    fn build_lb(
+        n_svc: usize,
+        ok_from: usize,
+    ) -> (Balancer<usize, usize, IoError, Conn>, Arc<AtomicUsize>) {
+        let count = Arc::new(AtomicUsize::new(0));
+        (Balancer::new((0..n_svc).collect(), Conn { count: count.clone(), ok_from }), count)
+    }
+
+    #[tokio::test]
+    async fn test_round_robin() {
+        let (lb, count_conn) = build_lb(3, 0);
+
+        let mut calls = [0; 3];
+        for _ in 0..9 {
+            calls[lb.run(|n| async move { Ok(*n) }).await.unwrap()] += 1;
+        }
+
+        // Calls are spread evenly, reusing connections
+        assert_eq!(calls, [3, 3, 3]);
+        assert_eq!(count_conn.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn test_failover() {
+        let (lb, _) = build_lb(3, 2);
+        let lb = lb.strategy(Strategy::Random);
+
+        for _ in 0..10 {
+            assert_eq!(lb.run(|n| async move { Ok(*n) }).await.unwrap(), 2);
+        }
+
+        let (lb, count_conn) = build_lb(3, 3);
+        let err = lb.run(|n| async move { Ok(*n) }).await.unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+        assert_eq!(count_conn.load(Ordering::Relaxed), 4);
+    }
+
+    #[tokio::test]
+    async fn test_single_flight() {
+        let (lb, count_conn) = build_lb(1, 0);
+
+        // Concurrent calls wait for the connection in progress
+        let run = || async { lb.run(|n| async move { Ok(*n) }).await.unwrap() };
+        assert_eq!(tokio::join!(run(), run(), run()), (0, 0, 0));
+        assert_eq!(count_conn.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_no_sources() {
+        let (lb, count_conn) = build_lb(0, 0);
+        let err = lb.run(|n| async move { Ok(*n) }).await.unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert_eq!(count_conn.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_least_in_flight() {
+        let (lb, _) = build_lb(3, 0);
+        let lb = lb.strategy(Strategy::LeastInFlight);
+        let release = &tokio::sync::Notify::new();
+
+        // Services busy with a pending call are avoided
+        let pending = |n: Arc<usize>| async move {
+            release.notified().await;
+            Ok(*n)
+        };
+        let (a, b, c) = tokio::join!(lb.run(pending), lb.run(pending), async {
+            tokio::task::yield_now().await;
+            assert_eq!((0..3).map(|i| lb.in_flight(i).unwrap()).sum::<usize>(), 2);
+            let n = lb.run(|n| async move { Ok(*n) }).await.unwrap();
+            release.notify_waiters();
+            n
+        });
+
+        let mut picked = [a.unwrap(), b.unwrap(), c];
+        picked.sort_unstable();
+        assert_eq!(picked, [0, 1, 2]);
+        assert_eq!(lb.in_flight(0), Some(0));
+    }
+}
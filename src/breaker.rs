@@ -0,0 +1,160 @@
+//! Per-service circuit breakers.
+//!
+//! Without circuit breakers, [`RoundRobin`](crate::RoundRobin) happily reconnects to a service
+//! that failed a second ago once it went through the whole service list. With circuit breakers,
+//! a service failing too many times in a row is considered down (the circuit is _open_), and is
+//! skipped for some time. Once this cooldown expires, the circuit is _half-open_: a single caller
+//! probes the service, and the circuit is closed again should it succeed.
+
+use std::{sync::Mutex, time::Duration};
+
+use tokio::time::Instant;
+
+/// Circuit breaker configuration.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// use tourniquet::breaker::CircuitBreaker;
+///
+/// // Skip a service for 30 seconds after 3 consecutive failures
+/// let breaker = CircuitBreaker::new(3, Duration::from_secs(30));
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: usize,
+    cooldown: Duration,
+}
+
+impl CircuitBreaker {
+    /// Open the circuit after `failure_threshold` consecutive failures, and keep it open for
+    /// `cooldown` before probing the service again.
+    pub fn new(failure_threshold: usize, cooldown: Duration) -> Self {
+        Self { failure_threshold: failure_threshold.max(1), cooldown }
+    }
+}
+
+/// State of the circuit of a service.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CircuitState {
+    /// The service is considered healthy, and is used normally.
+    Closed,
+    /// The service failed too many times, and is skipped until the cooldown expires.
+    Open,
+    /// The cooldown expired, and a single caller is probing the service.
+    HalfOpen,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum State {
+    Closed { failures: usize },
+    Open { until: Instant },
+    HalfOpen { since: Instant },
+}
+
+/// Circuit state of a single service.
+#[derive(Debug)]
+pub(crate) struct Breaker(Mutex<State>);
+
+impl Default for Breaker {
+    fn default() -> Self {
+        Self(Mutex::new(State::Closed { failures: 0 }))
+    }
+}
+
+impl Breaker {
+    pub(crate) fn state(&self) -> CircuitState {
+        match *self.0.lock().unwrap() {
+            State::Closed { .. } => CircuitState::Closed,
+            State::Open { .. } => CircuitState::Open,
+            State::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Wether the service can be connected to. Once the cooldown expired, only the first caller
+    /// is let through, as the probe. Should the probe never report back (e.g. it got cancelled),
+    /// another probe is let through after another cooldown.
+    pub(crate) fn acquire(&self, config: &CircuitBreaker) -> bool {
+        let mut state = self.0.lock().unwrap();
+        let now = Instant::now();
+
+        match *state {
+            State::Closed { .. } => true,
+            State::Open { until } if now >= until => {
+                *state = State::HalfOpen { since: now };
+                true
+            }
+            State::HalfOpen { since } if now >= since + config.cooldown => {
+                *state = State::HalfOpen { since: now };
+                true
+            }
+            State::Open { .. } | State::HalfOpen { .. } => false,
+        }
+    }
+
+    pub(crate) fn success(&self) {
+        let mut state = self.0.lock().unwrap();
+
+        // Late successes of calls started before the circuit opened are ignored.
+        if !matches!(*state, State::Open { .. }) {
+            *state = State::Closed { failures: 0 };
+        }
+    }
+
+    pub(crate) fn failure(&self, config: &CircuitBreaker) {
+        let mut state = self.0.lock().unwrap();
+        let open = State::Open { until: Instant::now() + config.cooldown };
+
+        *state = match *state {
+            State::Closed { failures } if failures + 1 < config.failure_threshold => {
+                State::Closed { failures: failures + 1 }
+            }
+            State::Closed { .. } | State::HalfOpen { .. } => open,
+            State::Open { until } => State::Open { until },
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_transitions() {
+        let config = CircuitBreaker::new(2, Duration::from_secs(10));
+        let breaker = Breaker::default();
+
+        // A success resets the failure count
+        breaker.failure(&config);
+        breaker.success();
+        breaker.failure(&config);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.acquire(&config));
+
+        breaker.failure(&config);
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.acquire(&config));
+
+        // Only a single probe is let through after the cooldown
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(breaker.acquire(&config));
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(!breaker.acquire(&config));
+
+        // A failed probe opens the circuit right away
+        breaker.failure(&config);
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        // A lost probe is replaced after another cooldown
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(breaker.acquire(&config));
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert!(!breaker.acquire(&config));
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert!(breaker.acquire(&config));
+
+        breaker.success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+}
@@ -0,0 +1,305 @@
+//! Validated construction of a [`RoundRobin`].
+
+use std::{
+    fmt::{Debug, Display},
+    marker::PhantomData,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+
+use crate::{
+    backoff::{Backoff, NoBackoff},
+    breaker::CircuitBreaker,
+    logging::Logging,
+    redact::{redact_passwords, Redact},
+    BuildError, Classify, Connector, Observer, RoundRobin,
+};
+
+/// Service a [`RoundRobin`] connects to first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Start {
+    /// The first service of the source list. This is the default.
+    #[default]
+    First,
+    /// A random service of the preferred tier, spreading clients across services.
+    Random,
+    /// The service at the given index of the source list.
+    At(usize),
+}
+
+/// Builder of a [`RoundRobin`], validating its configuration.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::{io::Error, time::Duration};
+/// # use tourniquet::{async_trait, Connector};
+/// use tourniquet::{BuildError, RoundRobin, Start};
+/// #
+/// # struct Conn;
+/// #
+/// # #[async_trait]
+/// # impl Connector<&'static str, (), Error> for Conn {
+/// #     async fn connect(&self, src: &&'static str) -> Result<(), Error> {
+/// #         Ok(())
+/// #     }
+/// # }
+///
+/// let rr = RoundRobin::builder()
+///     .sources(vec!["broker-1", "broker-2", "broker-3"])
+///     .connector(Conn)
+///     .connect_timeout(Duration::from_secs(5))
+///     .start(Start::Random)
+///     .build()
+///     .unwrap();
+///
+/// let res = RoundRobin::builder().sources(vec![]).connector(Conn).build();
+/// assert_eq!(res.err(), Some(BuildError::NoSources));
+/// ```
+pub struct RoundRobinBuilder<SvcSrc, Svc, E, Conn> {
+    tiers: Vec<Vec<SvcSrc>>,
+    connector: Option<Conn>,
+    max_attempts: Option<usize>,
+    backoff: Arc<dyn Backoff>,
+    rotation_backoff: Arc<dyn Backoff>,
+    connect_timeout: Option<Duration>,
+    run_timeout: Option<Duration>,
+    circuit_breaker: Option<CircuitBreaker>,
+    failback: Option<Duration>,
+    wait_for_sources: Option<Duration>,
+    start: Start,
+    observer: Option<Box<dyn Observer<SvcSrc, E>>>,
+    logging: Logging,
+    redact: Redact<SvcSrc>,
+    #[cfg(feature = "metrics")]
+    name: String,
+    _phantom: PhantomData<Svc>,
+}
+
+impl<SvcSrc, Svc, E, Conn> RoundRobinBuilder<SvcSrc, Svc, E, Conn>
+where
+    SvcSrc: Debug,
+    E: Classify + Display,
+    Conn: Connector<SvcSrc, Svc, E>,
+{
+    /// Start building a round-robin manager, see [`RoundRobin::builder`].
+    pub fn new() -> Self {
+        Self {
+            tiers: Vec::new(),
+            connector: None,
+            max_attempts: None,
+            backoff: Arc::new(NoBackoff),
+            rotation_backoff: Arc::new(NoBackoff),
+            connect_timeout: None,
+            run_timeout: None,
+            circuit_breaker: None,
+            failback: None,
+            wait_for_sources: None,
+            start: Start::First,
+            observer: None,
+            logging: Logging::default(),
+            redact: redact_passwords,
+            #[cfg(feature = "metrics")]
+            name: String::new(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Set the sources of the services, see [`RoundRobin::new`]. Must not be empty, unless
+    /// [`wait_for_sources`](Self::wait_for_sources) is set.
+    pub fn sources(self, sources: Vec<SvcSrc>) -> Self {
+        Self { tiers: vec![sources], ..self }
+    }
+
+    /// Set the sources of the services, grouped in priority tiers, see [`RoundRobin::tiered`].
+    pub fn tiers(self, tiers: Vec<Vec<SvcSrc>>) -> Self {
+        Self { tiers, ..self }
+    }
+
+    /// Set the connector, yielding a connected handler from a source. Required.
+    pub fn connector(self, connector: Conn) -> Self {
+        Self { connector: Some(connector), ..self }
+    }
+
+    /// See [`RoundRobin::max_attempts`]. Must not be zero.
+    pub fn max_attempts(self, count: usize) -> Self {
+        Self { max_attempts: Some(count), ..self }
+    }
+
+    /// See [`RoundRobin::backoff`].
+    pub fn backoff(self, backoff: impl Backoff + 'static) -> Self {
+        Self { backoff: Arc::new(backoff), ..self }
+    }
+
+    /// See [`RoundRobin::rotation_backoff`].
+    pub fn rotation_backoff(self, backoff: impl Backoff + 'static) -> Self {
+        Self { rotation_backoff: Arc::new(backoff), ..self }
+    }
+
+    /// See [`RoundRobin::connect_timeout`]. Must not be zero.
+    pub fn connect_timeout(self, timeout: Duration) -> Self {
+        Self { connect_timeout: Some(timeout), ..self }
+    }
+
+    /// See [`RoundRobin::run_timeout`]. Must not be zero.
+    pub fn run_timeout(self, timeout: Duration) -> Self {
+        Self { run_timeout: Some(timeout), ..self }
+    }
+
+    /// See [`RoundRobin::circuit_breaker`].
+    pub fn circuit_breaker(self, breaker: CircuitBreaker) -> Self {
+        Self { circuit_breaker: Some(breaker), ..self }
+    }
+
+    /// See [`RoundRobin::failback`]. Must not be zero.
+    pub fn failback(self, period: Duration) -> Self {
+        Self { failback: Some(period), ..self }
+    }
+
+    /// See [`RoundRobin::wait_for_sources`].
+    pub fn wait_for_sources(self, timeout: Duration) -> Self {
+        Self { wait_for_sources: Some(timeout), ..self }
+    }
+
+    /// Set the service to connect to first. Defaults to the first one.
+    pub fn start(self, start: Start) -> Self {
+        Self { start, ..self }
+    }
+
+    /// See [`RoundRobin::observer`].
+    pub fn observer(self, observer: impl Observer<SvcSrc, E> + 'static) -> Self {
+        Self { observer: Some(Box::new(observer)), ..self }
+    }
+
+    /// See [`RoundRobin::logging`].
+    pub fn logging(self, logging: Logging) -> Self {
+        Self { logging, ..self }
+    }
+
+    /// See [`RoundRobin::redact`].
+    pub fn redact(self, redact: Redact<SvcSrc>) -> Self {
+        Self { redact, ..self }
+    }
+
+    /// See [`RoundRobin::set_name`].
+    #[cfg(feature = "metrics")]
+    pub fn name(self, name: impl Into<String>) -> Self {
+        Self { name: name.into(), ..self }
+    }
+
+    /// Build the round-robin manager, or report the first invalid setting.
+    pub fn build(self) -> Result<RoundRobin<SvcSrc, Svc, E, Conn>, BuildError> {
+        let connector = self.connector.ok_or(BuildError::NoConnector)?;
+        let len = self.tiers.iter().map(Vec::len).sum();
+        if len == 0 && self.wait_for_sources.is_none() {
+            return Err(BuildError::NoSources);
+        }
+        if self.max_attempts == Some(0) {
+            return Err(BuildError::ZeroMaxAttempts);
+        }
+        if self.connect_timeout == Some(Duration::ZERO) {
+            return Err(BuildError::ZeroConnectTimeout);
+        }
+        if self.run_timeout == Some(Duration::ZERO) {
+            return Err(BuildError::ZeroRunTimeout);
+        }
+        if self.failback == Some(Duration::ZERO) {
+            return Err(BuildError::ZeroFailback);
+        }
+        let start = match self.start {
+            Start::First => 0,
+            Start::Random => {
+                // Sources are ordered by tier: pick within the first non-empty one
+                let preferred = self.tiers.iter().map(Vec::len).find(|&n| n > 0).unwrap_or(1);
+                fastrand::usize(..preferred)
+            }
+            Start::At(index) if index < len => index,
+            Start::At(index) => return Err(BuildError::StartOutOfRange { index, len }),
+        };
+
+        let rr = RoundRobin {
+            max_attempts: self.max_attempts,
+            backoff: self.backoff,
+            rotation_backoff: self.rotation_backoff,
+            connect_timeout: self.connect_timeout,
+            run_timeout: self.run_timeout,
+            circuit_breaker: self.circuit_breaker,
+            failback: self.failback,
+            wait_for_sources: self.wait_for_sources,
+            observer: self.observer,
+            logging: self.logging,
+            redact: self.redact,
+            #[cfg(feature = "metrics")]
+            name: self.name,
+            ..RoundRobin::tiered(self.tiers, connector)
+        };
+        rr.sources.current.store(start, Ordering::Relaxed);
+        rr.sources.set_logging(rr.logging, rr.redact);
+        rr.status.send_modify(|s| s.index = start);
+        Ok(rr)
+    }
+}
+
+impl<SvcSrc, Svc, E, Conn> Default for RoundRobinBuilder<SvcSrc, Svc, E, Conn>
+where
+    SvcSrc: Debug,
+    E: Classify + Display,
+    Conn: Connector<SvcSrc, Svc, E>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error as IoError;
+
+    use super::*;
+    use crate::async_trait;
+
+    struct Conn;
+
+    #[async_trait]
+    impl Connector<u16, u16, IoError> for Conn {
+        async fn connect(&self, src: &u16) -> Result<u16, IoError> {
+            Ok(*src)
+        }
+    }
+
+    type Builder = RoundRobinBuilder<u16, u16, IoError, Conn>;
+
+    #[tokio::test]
+    async fn test_build() {
+        let build = |builder: Builder| builder.build().err();
+        let valid = || Builder::new().sources(vec![1, 2, 3]).connector(Conn);
+
+        assert_eq!(build(Builder::new().sources(vec![1])), Some(BuildError::NoConnector));
+        assert_eq!(build(valid().sources(vec![])), Some(BuildError::NoSources));
+        assert_eq!(build(valid().tiers(vec![vec![], vec![]])), Some(BuildError::NoSources));
+        assert_eq!(build(valid().sources(vec![]).wait_for_sources(Duration::ZERO)), None);
+        assert_eq!(build(valid().max_attempts(0)), Some(BuildError::ZeroMaxAttempts));
+        assert_eq!(
+            build(valid().connect_timeout(Duration::ZERO)),
+            Some(BuildError::ZeroConnectTimeout)
+        );
+        assert_eq!(build(valid().run_timeout(Duration::ZERO)), Some(BuildError::ZeroRunTimeout));
+        assert_eq!(build(valid().failback(Duration::ZERO)), Some(BuildError::ZeroFailback));
+        assert_eq!(
+            build(valid().start(Start::At(3))),
+            Some(BuildError::StartOutOfRange { index: 3, len: 3 })
+        );
+
+        // The first call goes to the start service
+        let rr = valid().start(Start::At(2)).max_attempts(1).build().unwrap();
+        assert_eq!(rr.status().index, 2);
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await.unwrap(), 3);
+
+        // Random starts stay in the preferred tier
+        for _ in 0..10 {
+            let rr = valid().tiers(vec![vec![], vec![1, 2], vec![3]]).start(Start::Random);
+            let rr = rr.build().unwrap();
+            assert!(rr.run(|n| async move { Ok(*n) }).await.unwrap() < 3);
+        }
+    }
+}
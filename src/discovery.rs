@@ -0,0 +1,413 @@
+//! Discovery of services from DNS.
+//!
+//! Services are commonly published as a DNS name resolving to several addresses, or as SRV
+//! records. Rather than hardcoding the source list, [`DnsDiscovery`] resolves such a name into
+//! the sources of a [`RoundRobin`](crate::RoundRobin), and resolves it again once the records
+//! expire or when all services failed. See [`RoundRobin::discovery`](crate::RoundRobin::discovery).
+//!
+//! Resolution goes through the [`Resolver`] trait, implemented by [`SystemResolver`] on top of the
+//! system's resolver, and by [`StaticResolver`] from in-memory records for testing purposes.
+
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display, Error as FmtError, Formatter},
+    io::{Error as IoError, ErrorKind},
+    marker::PhantomData,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+
+use crate::{sources::Sources, task::Task};
+
+/// Delay before retrying a failed discovery.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Result of a discovery: the sources, and how long they may be used before discovering again.
+#[derive(Clone, Debug)]
+pub struct Discovered<SvcSrc> {
+    pub sources: Vec<SvcSrc>,
+    pub ttl: Duration,
+}
+
+/// Trait to be implemented by source providers. See
+/// [`RoundRobin::discovery`](crate::RoundRobin::discovery).
+#[async_trait]
+pub trait Discover<SvcSrc>: Send + Sync {
+    /// Discover the current list of sources.
+    async fn discover(&self) -> Result<Discovered<SvcSrc>, IoError>;
+
+    /// Convert the discovered sources with `f`, e.g. to build URLs from endpoints.
+    fn map<F, T>(self, f: F) -> Map<Self, F, SvcSrc>
+    where
+        Self: Sized,
+        F: Fn(SvcSrc) -> T + Send + Sync,
+    {
+        Map { inner: self, f, _phantom: PhantomData }
+    }
+}
+
+/// Converts the sources of another discovery. Usually built with [`Discover::map`].
+pub struct Map<D, F, SvcSrc> {
+    inner: D,
+    f: F,
+    _phantom: PhantomData<fn(SvcSrc)>,
+}
+
+#[async_trait]
+impl<D, F, SvcSrc, T> Discover<T> for Map<D, F, SvcSrc>
+where
+    D: Discover<SvcSrc>,
+    F: Fn(SvcSrc) -> T + Send + Sync,
+{
+    async fn discover(&self) -> Result<Discovered<T>, IoError> {
+        let Discovered { sources, ttl } = self.inner.discover().await?;
+        Ok(Discovered { sources: sources.into_iter().map(&self.f).collect(), ttl })
+    }
+}
+
+/// Address record (A or AAAA).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IpRecord {
+    pub addr: IpAddr,
+    pub ttl: Duration,
+}
+
+/// Service record, as defined by [RFC 2782](https://www.rfc-editor.org/rfc/rfc2782).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SrvRecord {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+    pub ttl: Duration,
+}
+
+/// DNS resolver used by [`DnsDiscovery`].
+#[async_trait]
+pub trait Resolver: Send + Sync {
+    /// Resolve the addresses of `name`.
+    async fn lookup_ip(&self, name: &str) -> Result<Vec<IpRecord>, IoError>;
+
+    /// Resolve the service records of `name`.
+    async fn lookup_srv(&self, name: &str) -> Result<Vec<SrvRecord>, IoError>;
+}
+
+#[async_trait]
+impl<R: Resolver + ?Sized> Resolver for Arc<R> {
+    async fn lookup_ip(&self, name: &str) -> Result<Vec<IpRecord>, IoError> {
+        (**self).lookup_ip(name).await
+    }
+
+    async fn lookup_srv(&self, name: &str) -> Result<Vec<SrvRecord>, IoError> {
+        (**self).lookup_srv(name).await
+    }
+}
+
+/// Resolver backed by the system's resolver, through [`tokio::net::lookup_host`].
+///
+/// The system resolver neither exposes record TTLs, which are all set to a fixed value, nor
+/// supports SRV records.
+#[derive(Clone, Copy, Debug)]
+pub struct SystemResolver {
+    ttl: Duration,
+}
+
+impl SystemResolver {
+    /// Build a resolver reporting all records as valid for `ttl`.
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl }
+    }
+}
+
+#[async_trait]
+impl Resolver for SystemResolver {
+    async fn lookup_ip(&self, name: &str) -> Result<Vec<IpRecord>, IoError> {
+        let addrs = tokio::net::lookup_host((name, 0)).await?;
+        Ok(addrs.map(|addr| IpRecord { addr: addr.ip(), ttl: self.ttl }).collect())
+    }
+
+    async fn lookup_srv(&self, _name: &str) -> Result<Vec<SrvRecord>, IoError> {
+        Err(IoError::new(ErrorKind::Unsupported, "SRV records are not supported"))
+    }
+}
+
+/// Resolver serving in-memory records, that can be changed at any time.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// use tourniquet::discovery::{IpRecord, StaticResolver};
+///
+/// let resolver = StaticResolver::new();
+/// let ttl = Duration::from_secs(60);
+/// resolver.set_ip("db.example.com", vec![IpRecord { addr: "10.0.0.1".parse().unwrap(), ttl }]);
+/// ```
+#[derive(Debug, Default)]
+pub struct StaticResolver {
+    ips: Mutex<HashMap<String, Vec<IpRecord>>>,
+    srvs: Mutex<HashMap<String, Vec<SrvRecord>>>,
+}
+
+impl StaticResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the address records of `name`.
+    pub fn set_ip(&self, name: impl Into<String>, records: Vec<IpRecord>) {
+        self.ips.lock().unwrap().insert(name.into(), records);
+    }
+
+    /// Set the service records of `name`.
+    pub fn set_srv(&self, name: impl Into<String>, records: Vec<SrvRecord>) {
+        self.srvs.lock().unwrap().insert(name.into(), records);
+    }
+}
+
+#[async_trait]
+impl Resolver for StaticResolver {
+    async fn lookup_ip(&self, name: &str) -> Result<Vec<IpRecord>, IoError> {
+        lookup(&self.ips, name)
+    }
+
+    async fn lookup_srv(&self, name: &str) -> Result<Vec<SrvRecord>, IoError> {
+        lookup(&self.srvs, name)
+    }
+}
+
+fn lookup<T: Clone>(
+    records: &Mutex<HashMap<String, Vec<T>>>,
+    name: &str,
+) -> Result<Vec<T>, IoError> {
+    let records = records.lock().unwrap();
+    let not_found = || IoError::new(ErrorKind::NotFound, format!("no record for {}", name));
+    records.get(name).cloned().ok_or_else(not_found)
+}
+
+/// Network endpoint discovered by [`DnsDiscovery`]: an address, or the target of a SRV record.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Endpoint {
+    pub host: String,
+    pub port: u16,
+}
+
+impl Display for Endpoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)
+        } else {
+            write!(f, "{}:{}", self.host, self.port)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Query {
+    Ip { name: String, port: u16 },
+    Srv { name: String },
+}
+
+/// Discovery of services from a DNS name.
+///
+/// Either the name resolves to the addresses of the services (A/AAAA records), which all listen
+/// on the same port, or to SRV records. SRV targets are ordered by ascending priority, and by a
+/// weighted random shuffle within a priority, as mandated by
+/// [RFC 2782](https://www.rfc-editor.org/rfc/rfc2782).
+///
+/// The sources are discovered again once the shortest TTL of the records expires, bounded to
+/// 1 second to 5 minutes by default.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// use tourniquet::discovery::{Discover, DnsDiscovery, SystemResolver};
+///
+/// let discovery = DnsDiscovery::ip(SystemResolver::new(Duration::from_secs(30)), "localhost", 80)
+///     .map(|ep| format!("http://{}", ep));
+/// ```
+#[derive(Clone, Debug)]
+pub struct DnsDiscovery<R> {
+    resolver: R,
+    query: Query,
+    min_ttl: Duration,
+    max_ttl: Duration,
+}
+
+impl<R: Resolver> DnsDiscovery<R> {
+    /// Discover the services from the addresses `name` resolves to, all listening on `port`.
+    pub fn ip(resolver: R, name: impl Into<String>, port: u16) -> Self {
+        Self::new(resolver, Query::Ip { name: name.into(), port })
+    }
+
+    /// Discover the services from the SRV records of `name`, e.g. `_ldap._tcp.example.com`.
+    pub fn srv(resolver: R, name: impl Into<String>) -> Self {
+        Self::new(resolver, Query::Srv { name: name.into() })
+    }
+
+    fn new(resolver: R, query: Query) -> Self {
+        let (min_ttl, max_ttl) = (Duration::from_secs(1), Duration::from_secs(300));
+        Self { resolver, query, min_ttl, max_ttl }
+    }
+
+    /// Bound the TTL of the records, so that services are discovered again at least every `max`,
+    /// and at most every `min`.
+    pub fn ttl_bounds(self, min: Duration, max: Duration) -> Self {
+        Self { min_ttl: min, max_ttl: max.max(min), ..self }
+    }
+
+    fn ttl(&self, ttls: impl Iterator<Item = Duration>) -> Duration {
+        ttls.min().unwrap_or(self.min_ttl).clamp(self.min_ttl, self.max_ttl)
+    }
+}
+
+#[async_trait]
+impl<R: Resolver> Discover<Endpoint> for DnsDiscovery<R> {
+    async fn discover(&self) -> Result<Discovered<Endpoint>, IoError> {
+        match self.query {
+            Query::Ip { ref name, port } => {
+                let records = self.resolver.lookup_ip(name).await?;
+                Ok(Discovered {
+                    ttl: self.ttl(records.iter().map(|r| r.ttl)),
+                    sources: records
+                        .into_iter()
+                        .map(|r| Endpoint { host: r.addr.to_string(), port })
+                        .collect(),
+                })
+            }
+            Query::Srv { ref name } => {
+                let records = self.resolver.lookup_srv(name).await?;
+                Ok(Discovered {
+                    ttl: self.ttl(records.iter().map(|r| r.ttl)),
+                    sources: srv_order(records)
+                        .into_iter()
+                        .map(|r| Endpoint {
+                            host: r.target.trim_end_matches('.').into(),
+                            port: r.port,
+                        })
+                        .collect(),
+                })
+            }
+        }
+    }
+}
+
+/// Order SRV records by ascending priority, shuffling records of the same priority according to
+/// their weights. A target of `.` means the service is not available, and is dropped.
+fn srv_order(mut records: Vec<SrvRecord>) -> Vec<SrvRecord> {
+    records.retain(|r| r.target != ".");
+    records.sort_by_key(|r| r.priority);
+
+    let mut ordered = Vec::with_capacity(records.len());
+    while !records.is_empty() {
+        let n_prio = records.iter().take_while(|r| r.priority == records[0].priority).count();
+        let mut group: Vec<_> = records.drain(..n_prio).collect();
+
+        while !group.is_empty() {
+            // Zero-weight records are only picked once all others are
+            let total: u32 = group.iter().map(|r| u32::from(r.weight)).sum();
+            let index = if total == 0 {
+                fastrand::usize(..group.len())
+            } else {
+                let mut pick = fastrand::u32(..total);
+                group
+                    .iter()
+                    .position(|r| match pick.checked_sub(u32::from(r.weight)) {
+                        Some(rest) => {
+                            pick = rest;
+                            false
+                        }
+                        None => true,
+                    })
+                    .unwrap_or(0)
+            };
+            ordered.push(group.remove(index));
+        }
+    }
+
+    ordered
+}
+
+/// Spawn a task replacing the sources each time they are discovered, which happens once they
+/// expire or when `sources` ask for a refresh.
+pub(crate) fn spawn<SvcSrc, D>(sources: Arc<Sources<SvcSrc>>, discovery: D) -> Task
+where
+    SvcSrc: PartialEq + Send + Sync + 'static,
+    D: Discover<SvcSrc> + 'static,
+{
+    Task::spawn(async move {
+        loop {
+            let ttl = match discovery.discover().await {
+                Ok(Discovered { sources: srcs, ttl }) if srcs.is_empty() => {
+                    let (logging, _) = sources.logging();
+                    logging.log_discovery(&sources.discovery_log, None);
+                    ttl
+                }
+                Ok(Discovered { sources: srcs, ttl }) => {
+                    sources.replace(srcs);
+                    ttl
+                }
+                Err(e) => {
+                    let (logging, _) = sources.logging();
+                    logging.log_discovery(&sources.discovery_log, Some(&e));
+                    RETRY_DELAY
+                }
+            };
+
+            let _ = tokio::time::timeout(ttl, sources.refresh.notified()).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn srv(priority: u16, weight: u16, port: u16) -> SrvRecord {
+        let ttl = Duration::from_secs(priority.into());
+        SrvRecord { priority, weight, port, target: format!("srv{}.example.", port), ttl }
+    }
+
+    #[tokio::test]
+    async fn test_srv() {
+        let resolver = Arc::new(StaticResolver::new());
+        let discovery = DnsDiscovery::srv(resolver.clone(), "_svc._tcp.example")
+            .ttl_bounds(Duration::from_secs(5), Duration::from_secs(60));
+
+        let mut unavailable = srv(0, 0, 0);
+        unavailable.target = ".".into();
+        let records = vec![srv(20, 0, 4), srv(10, 0, 1), srv(20, 1, 3), unavailable, srv(10, 0, 2)];
+        resolver.set_srv("_svc._tcp.example", records);
+
+        for _ in 0..20 {
+            let Discovered { sources, ttl } = discovery.discover().await.unwrap();
+            let ports: Vec<_> = sources.iter().map(|ep| ep.port).collect();
+
+            // Priorities are ordered, and the weighted record goes first
+            assert!(ports == [1, 2, 3, 4] || ports == [2, 1, 3, 4], "{:?}", ports);
+            assert_eq!(sources[0].host, format!("srv{}.example", ports[0]));
+            assert_eq!(ttl, Duration::from_secs(5));
+        }
+
+        let err = DnsDiscovery::srv(resolver, "_other._tcp.example").discover().await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_ip() {
+        let resolver = StaticResolver::new();
+        let ttl = Duration::from_secs(600);
+        let records = ["10.0.0.1", "::1"].map(|a| IpRecord { addr: a.parse().unwrap(), ttl });
+        resolver.set_ip("svc.example", records.to_vec());
+
+        let discovery = DnsDiscovery::ip(resolver, "svc.example", 80).map(|ep| ep.to_string());
+        let Discovered { sources, ttl } = discovery.discover().await.unwrap();
+
+        assert_eq!(sources, ["10.0.0.1:80", "[::1]:80"]);
+        assert_eq!(ttl, Duration::from_secs(300));
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    any::Any,
+    fmt::{Debug, Display, Error as FmtError, Formatter},
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{Classification, Classify, Next};
+
+/// Errors raised by tourniquet itself, rather than by the service or its connector.
+///
+/// The `run` functions returning the error type of the [`RoundRobin`](crate::RoundRobin) convert
+/// them with `From<Error>`, so that they can be bubbled up to the caller. The other ones report
+/// them as [`RunError::Tourniquet`], and can be used with error types that are not convertible.
+///
+/// # Example
+///
+/// ```rust
+/// enum MyError {
+///     Service(String),
+///     RoundRobin(tourniquet::Error),
+/// }
+///
+/// impl From<tourniquet::Error> for MyError {
+///     fn from(e: tourniquet::Error) -> Self {
+///         Self::RoundRobin(e)
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The connector did not yield a service within the configured connect timeout.
+    ConnectTimeout(Duration),
+    /// The run function did not complete within the configured run timeout.
+    RunTimeout(Duration),
+    /// The overall deadline of the call expired before any attempt succeeded.
+    DeadlineExceeded,
+    /// The circuit of every service is open, see [`CircuitBreaker`](crate::breaker::CircuitBreaker).
+    CircuitOpen,
+    /// Every service is unhealthy, as reported by the [`HealthCheck`](crate::HealthCheck).
+    Unhealthy,
+    /// The source list is empty, see
+    /// [`RoundRobin::wait_for_sources`](crate::RoundRobin::wait_for_sources).
+    NoSources,
+    /// The connection failed without moving on to the next service, with the given error shared
+    /// by all the calls that waited for it, rather than connecting on their own.
+    ConnectFailed(ConnectError),
+}
+
+impl Next for Error {
+    fn is_next(&self) -> bool {
+        match self {
+            Self::ConnectTimeout(_) | Self::RunTimeout(_) => true,
+            Self::DeadlineExceeded => false,
+            Self::CircuitOpen | Self::Unhealthy => true,
+            Self::NoSources | Self::ConnectFailed(_) => false,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            Self::ConnectTimeout(t) => write!(f, "connection timed out after {:?}", t),
+            Self::RunTimeout(t) => write!(f, "run timed out after {:?}", t),
+            Self::DeadlineExceeded => write!(f, "deadline exceeded"),
+            Self::CircuitOpen => write!(f, "circuit open"),
+            Self::Unhealthy => write!(f, "service unhealthy"),
+            Self::NoSources => write!(f, "no sources"),
+            Self::ConnectFailed(e) => write!(f, "connection failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Error of a connection shared by concurrent calls, see [`Error::ConnectFailed`]. Two of them
+/// are equal if they are the same error.
+#[derive(Clone)]
+pub struct ConnectError(Arc<dyn Shared>);
+
+/// Error that can be shared by concurrent calls.
+trait Shared: Display + Send + Sync + 'static {
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<E: Display + Send + Sync + 'static> Shared for E {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl ConnectError {
+    /// The error returned by the connector, should it be of type `E`.
+    pub fn downcast_ref<E: 'static>(&self) -> Option<&E> {
+        // Not the `Arc` itself, which is `Shared` as well
+        (*self.0).as_any().downcast_ref()
+    }
+}
+
+impl PartialEq for ConnectError {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for ConnectError {}
+
+impl Debug for ConnectError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        f.debug_tuple("ConnectError").field(&format_args!("{}", self.0)).finish()
+    }
+}
+
+impl Display for ConnectError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        Display::fmt(&self.0, f)
+    }
+}
+
+/// Error of an attempt, be it returned by the service or raised by tourniquet itself.
+#[derive(Debug, PartialEq)]
+pub enum RunError<E> {
+    /// Error returned by the service or its connector.
+    Service(E),
+    /// Error of a connection failing without moving on to the next service, shared by all the
+    /// calls that waited for it rather than connecting on their own.
+    Connect(Arc<E>),
+    /// Error raised by tourniquet itself, like a timeout.
+    Tourniquet(Error),
+}
+
+impl<E> RunError<E> {
+    /// Convert into the error type of the service. A shared connection error is converted from
+    /// [`Error::ConnectFailed`].
+    pub fn into_error(self) -> E
+    where
+        E: From<Error> + Display + Send + Sync + 'static,
+    {
+        match self {
+            Self::Service(e) => e,
+            Self::Connect(e) => Error::ConnectFailed(ConnectError(e)).into(),
+            Self::Tourniquet(e) => e.into(),
+        }
+    }
+}
+
+impl<E: Classify> Classify for RunError<E> {
+    fn classify(&self) -> Classification {
+        match self {
+            Self::Service(e) => e.classify(),
+            Self::Connect(e) => e.classify(),
+            Self::Tourniquet(e) => e.classify(),
+        }
+    }
+}
+
+impl<E> From<Error> for RunError<E> {
+    fn from(e: Error) -> Self {
+        Self::Tourniquet(e)
+    }
+}
+
+impl<E: Display> Display for RunError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            Self::Service(e) => Display::fmt(e, f),
+            Self::Connect(e) => Display::fmt(e, f),
+            Self::Tourniquet(e) => Display::fmt(e, f),
+        }
+    }
+}
+
+impl<E: std::error::Error + 'static> std::error::Error for RunError<E> {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Service(e) => Some(e),
+            Self::Connect(e) => Some(&**e),
+            Self::Tourniquet(e) => Some(e),
+        }
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(e: Error) -> Self {
+        use std::io::ErrorKind::*;
+        let kind = match &e {
+            Error::ConnectTimeout(_) | Error::RunTimeout(_) | Error::DeadlineExceeded => TimedOut,
+            Error::CircuitOpen | Error::Unhealthy => Other,
+            Error::NoSources => NotFound,
+            Error::ConnectFailed(e) => e.downcast_ref::<Self>().map_or(Other, Self::kind),
+        };
+        Self::new(kind, e)
+    }
+}
+
+/// Invalid configuration, as reported by
+/// [`RoundRobinBuilder::build`](crate::RoundRobinBuilder::build).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BuildError {
+    /// No connector was set.
+    NoConnector,
+    /// The source list is empty, and calls would not wait for sources to be added.
+    NoSources,
+    /// The maximum number of attempts is zero, so that no call could ever succeed.
+    ZeroMaxAttempts,
+    /// The connect timeout is zero, so that no connection could ever succeed.
+    ZeroConnectTimeout,
+    /// The run timeout is zero, so that no call could ever succeed.
+    ZeroRunTimeout,
+    /// The failback period is zero.
+    ZeroFailback,
+    /// The service to start with is out of the source list.
+    StartOutOfRange {
+        /// Index of the service to start with.
+        index: usize,
+        /// Number of sources.
+        len: usize,
+    },
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            Self::NoConnector => write!(f, "no connector was set"),
+            Self::NoSources => write!(f, "the source list is empty"),
+            Self::ZeroMaxAttempts => write!(f, "the maximum number of attempts must not be zero"),
+            Self::ZeroConnectTimeout => write!(f, "the connect timeout must not be zero"),
+            Self::ZeroRunTimeout => write!(f, "the run timeout must not be zero"),
+            Self::ZeroFailback => write!(f, "the failback period must not be zero"),
+            Self::StartOutOfRange { index, len } => {
+                write!(f, "cannot start with service {}, there are only {} sources", index, len)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Failure of a single attempt, as reported by [`Exhausted`].
+#[derive(Debug)]
+pub struct AttemptError<E> {
+    /// Index of the service in the round-robin's source list, zero if the list is empty.
+    pub index: usize,
+    /// Representation of the service's source, see
+    /// [`RoundRobin::redact`](crate::RoundRobin::redact). Empty if the list is empty.
+    pub source: String,
+    /// Error of the attempt.
+    pub error: RunError<E>,
+    /// Time elapsed since the start of the call when the attempt failed.
+    pub elapsed: Duration,
+}
+
+/// Errors of every failed attempt of a call, returned by
+/// [`RoundRobin::run_with_errors`](crate::RoundRobin::run_with_errors).
+///
+/// The last error is the one the other `run` functions would have returned.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::io::Error;
+/// # use tourniquet::{async_trait, Connector, RoundRobin, RunOptions};
+/// #
+/// # struct Conn;
+/// #
+/// # #[async_trait]
+/// # impl Connector<&'static str, (), Error> for Conn {
+/// #     async fn connect(&self, src: &&'static str) -> Result<(), Error> {
+/// #         Err(std::io::ErrorKind::ConnectionRefused.into())
+/// #     }
+/// # }
+/// #
+/// # #[tokio::main]
+/// # async fn main() {
+/// let rr = RoundRobin::new(vec!["broker-1", "broker-2", "broker-3"], Conn).max_attempts(3);
+/// let errors = rr.run_with_errors(RunOptions::new(), |_, _| async { Ok(()) }).await.unwrap_err();
+///
+/// assert_eq!(errors.failures().len(), 3);
+/// assert_eq!(errors.failures()[2].source, r#""broker-3""#);
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Exhausted<E> {
+    /// Never empty.
+    failures: Vec<AttemptError<E>>,
+}
+
+impl<E> Exhausted<E> {
+    pub(crate) fn new(failures: Vec<AttemptError<E>>) -> Self {
+        debug_assert!(!failures.is_empty());
+        Self { failures }
+    }
+
+    /// Failures of all attempts, in order.
+    pub fn failures(&self) -> &[AttemptError<E>] {
+        &self.failures
+    }
+
+    /// Failures of all attempts, in order.
+    pub fn into_failures(self) -> Vec<AttemptError<E>> {
+        self.failures
+    }
+
+    /// The last error.
+    pub fn last(&self) -> &RunError<E> {
+        &self.failures[self.failures.len() - 1].error
+    }
+
+    /// The last error.
+    pub fn into_last(mut self) -> RunError<E> {
+        self.failures.pop().expect("no failure").error
+    }
+}
+
+impl<E: Display> Display for Exhausted<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{} attempts failed", self.failures.len())?;
+        for (i, failure) in self.failures.iter().enumerate() {
+            let sep = if i == 0 { ": " } else { "; " };
+            write!(
+                f,
+                "{}service {} ({}) after {:?}: {}",
+                sep, failure.index, failure.source, failure.elapsed, failure.error
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<E> std::error::Error for Exhausted<E>
+where
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.last())
+    }
+}
@@ -0,0 +1,62 @@
+//! Background health checking of services.
+
+use std::{
+    fmt::Debug,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+
+use tokio::{
+    task::JoinSet,
+    time::{interval, timeout, MissedTickBehavior},
+};
+
+use crate::{sources::Sources, task::Task, HealthCheck};
+
+/// Spawn a task probing every service each `period`, updating their health status.
+///
+/// Services are probed concurrently, and a probe taking longer than `period` counts as a failed
+/// one, so that a hanging service neither delays the others nor the next round.
+///
+/// # Panics
+///
+/// This panics if `period` is zero.
+pub(crate) fn spawn<SvcSrc, H>(sources: Arc<Sources<SvcSrc>>, check: H, period: Duration) -> Task
+where
+    SvcSrc: Debug + Send + Sync + 'static,
+    H: HealthCheck<SvcSrc> + Send + Sync + 'static,
+{
+    assert!(!period.is_zero(), "the health check period must not be zero");
+    let check = Arc::new(check);
+
+    Task::spawn(async move {
+        let mut ticks = interval(period);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticks.tick().await;
+
+            let snapshot = sources.snapshot();
+            let mut probes = JoinSet::new();
+            for (index, source) in snapshot.iter().cloned().enumerate() {
+                let check = check.clone();
+                probes.spawn(async move {
+                    let healthy = timeout(period, check.check(&source.src)).await.unwrap_or(false);
+                    (index, source, healthy)
+                });
+            }
+
+            while let Some(probe) = probes.join_next().await {
+                // A panicking probe leaves the health of its service as is
+                let (index, source, healthy) = match probe {
+                    Ok(probe) => probe,
+                    Err(_) => continue,
+                };
+                if source.healthy.swap(healthy, Ordering::Relaxed) != healthy {
+                    let (logging, redact) = sources.logging();
+                    logging.log_health(index, &source, redact, healthy);
+                }
+            }
+        }
+    })
+}
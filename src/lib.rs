@@ -8,7 +8,8 @@
 //!
 //! Disclaimer: this library is not for load-balancing between a set of providers! It will connect
 //! to _one_ provider, and only use this one provider as long as it is up. Tourniquet is meant for
-//! resiliency and not for load balancing.
+//! resiliency and not for load balancing. Should you need load balancing, see
+//! [`Balancer`](balancer::Balancer).
 //!
 //! # Example
 //!
@@ -45,6 +46,27 @@
 //! }
 //! ```
 //!
+//! # Tracing
+//!
+//! With the `trace` feature, every call of a [`RoundRobin`] is traced, and each of its attempts
+//! gets its own `attempt` span with the following fields:
+//!
+//! - `attempt`: attempt number, starting at 1
+//! - `index` and `service`: index and source of the service the attempt ran against
+//! - `reconnect`: wether the attempt connected to the service
+//! - `outcome`: `ok`, or the classification of the error: `fatal`, `retry`, `reconnect`, `next`, or
+//!   `skipped`
+//! - `error`: the error of the attempt, if any
+//!
+//! Moving on to the next service is recorded as a `failover` event of the attempt span, with the
+//! `from` and `to` indices.
+//!
+//! The `otel-semconv` feature adds fields named after the OpenTelemetry semantic conventions:
+//! `otel.status_code`, `otel.status_description` and `error.type`, the latter being the outcome.
+//! Tourniquet does not export anything itself: install the [`tracing-opentelemetry`] layer in your
+//! subscriber to turn these fields into span status and attributes, so that a single trace shows
+//! the whole retry chain.
+//!
 //! # Integrations
 //!
 //! Tourniquet provides some integrations with existing crates, which shorthand functions to reduce
@@ -60,22 +82,54 @@
 //! [`tonic`]: https://lib.rs/tonic
 //! [`tourniquet-celery`]: https://lib.rs/tourniquet-celery
 //! [`tourniquet-tonic`]: https://lib.rs/tourniquet-tonic
+//! [`tracing-opentelemetry`]: https://lib.rs/tracing-opentelemetry
 
 use core::future::Future;
 use std::{
     fmt::{Debug, Display},
     marker::PhantomData,
-    sync::atomic::{AtomicUsize, Ordering},
+    pin::pin,
+    sync::atomic::Ordering,
     sync::Arc,
+    time::Duration,
 };
 
 pub use async_trait::async_trait;
-use tokio::sync::RwLock;
+use tokio::{sync::watch, time::Instant};
 #[cfg(feature = "tracing")]
-use tracing::{
-    field::{debug, display, Empty},
-    instrument, Instrument, Span,
-};
+use tracing::{field::display, instrument, Instrument, Span};
+
+pub mod backoff;
+pub mod balancer;
+pub mod breaker;
+mod builder;
+pub mod discovery;
+mod error;
+mod health;
+pub mod logging;
+mod redact;
+mod slot;
+mod sources;
+pub mod stats;
+mod task;
+#[cfg(feature = "metrics")]
+mod telemetry;
+#[cfg(all(test, any(feature = "metrics", feature = "tracing")))]
+mod testing;
+#[cfg(feature = "tracing")]
+mod trace;
+
+use backoff::{Backoff, NoBackoff};
+use breaker::{CircuitBreaker, CircuitState};
+pub use builder::{RoundRobinBuilder, Start};
+use discovery::Discover;
+pub use error::{AttemptError, BuildError, ConnectError, Error, Exhausted, RunError};
+use logging::{Limiter, Logging};
+pub use redact::{redact_passwords, Redact, Redacted};
+use slot::{Acquired, Connecting, Connection, Slot};
+use sources::{Source, Sources};
+use stats::SourceStats;
+use task::Task;
 
 /// Trait indicating wether an error mandates trying the next service.
 ///
@@ -132,6 +186,80 @@ impl Next for std::io::Error {
     }
 }
 
+/// What to do after an error, as returned by [`Classify`].
+///
+/// Retryable classifications can carry a delay suggested by the service (e.g. from a `Retry-After`
+/// header), which is waited before the next attempt unless the backoff policy waits longer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Classification {
+    /// The error is fatal, and is bubbled up to the caller.
+    Fatal,
+    /// Retry against the same connection, e.g. the service is momentarily overloaded.
+    Retry(Option<Duration>),
+    /// Drop the connection, and reconnect to the same service, e.g. the session went stale.
+    Reconnect(Option<Duration>),
+    /// Drop the connection, and try the next service. This is a [`Next`] error.
+    Next(Option<Duration>),
+}
+
+impl Classification {
+    /// Delay suggested by the service before trying again, if any.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match *self {
+            Self::Fatal => None,
+            Self::Retry(after) | Self::Reconnect(after) | Self::Next(after) => after,
+        }
+    }
+}
+
+/// Extended version of [`Next`], classifying errors in more than fatal or next errors.
+///
+/// It is implemented for all [`Next`] types, fatal errors being classified as
+/// [`Classification::Fatal`], and next errors as [`Classification::Next`]. Implement it instead of
+/// [`Next`] for finer control.
+///
+/// Every retryable classification counts as an attempt, but only next errors are reported to the
+/// circuit breaker of the service.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// use tourniquet::{Classification, Classify};
+///
+/// enum MyError {
+///     NotFound,
+///     SessionExpired,
+///     Overloaded { retry_after: Duration },
+///     InternalError,
+/// }
+///
+/// impl Classify for MyError {
+///     fn classify(&self) -> Classification {
+///         match self {
+///             Self::NotFound => Classification::Fatal,
+///             Self::SessionExpired => Classification::Reconnect(None),
+///             Self::Overloaded { retry_after } => Classification::Retry(Some(*retry_after)),
+///             Self::InternalError => Classification::Next(None),
+///         }
+///     }
+/// }
+/// ```
+pub trait Classify {
+    /// Classify the error, telling what to do next.
+    fn classify(&self) -> Classification;
+}
+
+impl<T: Next + ?Sized> Classify for T {
+    fn classify(&self) -> Classification {
+        if self.is_next() {
+            Classification::Next(None)
+        } else {
+            Classification::Fatal
+        }
+    }
+}
+
 /// Trait to be implemented by connector types. Used to get a connected service from its connection
 /// information.
 ///
@@ -162,6 +290,193 @@ pub trait Connector<SvcSrc, Svc, E> {
     async fn connect(&self, src: &SvcSrc) -> Result<Svc, E>;
 }
 
+/// Trait to be implemented by health checkers, probing services in the background. See
+/// [`RoundRobin::health_check`].
+///
+/// # Example
+///
+/// ```rust
+/// # use async_trait::async_trait;
+/// use std::net::IpAddr;
+/// use tokio::net::TcpStream;
+/// use tourniquet::HealthCheck;
+///
+/// struct Check(u16);
+///
+/// #[async_trait]
+/// impl HealthCheck<IpAddr> for Check {
+///     async fn check(&self, src: &IpAddr) -> bool {
+///         let Check(port) = self;
+///         TcpStream::connect((*src, *port)).await.is_ok()
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait HealthCheck<SvcSrc> {
+    /// Probe the service, returning wether it is healthy.
+    async fn check(&self, src: &SvcSrc) -> bool;
+}
+
+/// Trait to be implemented by observers of a [`RoundRobin`]'s state changes, e.g. to page after
+/// repeated failovers or emit metrics. See [`RoundRobin::observer`].
+///
+/// Callbacks are called inline by the calls to `run`, and should not block. They all do nothing by
+/// default.
+///
+/// # Example
+///
+/// ```rust
+/// use tourniquet::Observer;
+///
+/// struct Log;
+///
+/// impl Observer<String, std::io::Error> for Log {
+///     fn on_failover(&self, from: usize, from_src: &String, to: usize, to_src: &String) {
+///         println!("Failing over from {} ({}) to {} ({})", from_src, from, to_src, to);
+///     }
+/// }
+/// ```
+#[allow(unused_variables)]
+pub trait Observer<SvcSrc, E>: Send + Sync {
+    /// A connection to the service at `index` in the source list is being attempted.
+    fn on_connect(&self, index: usize, src: &SvcSrc) {}
+
+    /// The service at `index` was connected to.
+    fn on_connected(&self, index: usize, src: &SvcSrc) {}
+
+    /// The connection to the service at `index` failed.
+    fn on_connect_error(&self, index: usize, src: &SvcSrc, error: &RunError<E>) {}
+
+    /// A call against the service at `index` failed with an error classified as
+    /// [`Next`](Classification::Next).
+    fn on_next_error(&self, index: usize, src: &SvcSrc, error: &RunError<E>) {}
+
+    /// The service at `from` was moved on from, to the one at `to`, be it because it failed, or
+    /// because it was skipped.
+    fn on_failover(&self, from: usize, from_src: &SvcSrc, to: usize, to_src: &SvcSrc) {}
+
+    /// A call gave up after using all its attempts, after all services were skipped, or once its
+    /// deadline was reached. The failures of all attempts are collected for the observer, whatever
+    /// the `run` function used.
+    fn on_exhausted(&self, errors: &Exhausted<E>) {}
+}
+
+/// Per-call options of [`RoundRobin::run_with`], overriding the round-robin's configuration.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// use tourniquet::{backoff::Constant, RunOptions};
+///
+/// let opts = RunOptions::new()
+///     .backoff(Constant(Duration::from_millis(100)))
+///     .timeout(Duration::from_secs(5));
+/// ```
+#[derive(Clone, Default)]
+pub struct RunOptions {
+    backoff: Option<Arc<dyn Backoff>>,
+    rotation_backoff: Option<Arc<dyn Backoff>>,
+    deadline: Option<Instant>,
+    timeout: Option<Duration>,
+}
+
+impl RunOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Override the backoff policy applied between attempts.
+    pub fn backoff(self, backoff: impl Backoff + 'static) -> Self {
+        Self { backoff: Some(Arc::new(backoff)), ..self }
+    }
+
+    /// Override the backoff policy applied between full rotations of the service list.
+    pub fn rotation_backoff(self, backoff: impl Backoff + 'static) -> Self {
+        Self { rotation_backoff: Some(Arc::new(backoff)), ..self }
+    }
+
+    /// Set a deadline for the whole call, all attempts included. Once it is reached, the in-flight
+    /// attempt is cancelled, no new attempt is started, and [`Error::DeadlineExceeded`] is
+    /// returned.
+    pub fn deadline(self, deadline: Instant) -> Self {
+        Self { deadline: Some(deadline), ..self }
+    }
+
+    /// Same as [`deadline`](Self::deadline), relative to the start of the call.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Self { timeout: Some(timeout), ..self }
+    }
+
+    /// Effective deadline of a call started at `start`.
+    fn deadline_from(&self, start: Instant) -> Option<Instant> {
+        earliest(self.deadline, self.timeout.and_then(|t| start.checked_add(t)))
+    }
+}
+
+/// Information about the attempt being run, given to the run function of
+/// [`RoundRobin::run_with_attempt`].
+#[derive(Clone, Copy, Debug)]
+pub struct Attempt {
+    index: usize,
+    number: usize,
+    deadline: Option<Instant>,
+}
+
+impl Attempt {
+    /// Index of the service in the round-robin's source list.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Attempt number, starting at 1.
+    pub fn number(&self) -> usize {
+        self.number
+    }
+
+    /// Instant at which this attempt will be cancelled, be it because of the run timeout or the
+    /// call's deadline, if any.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Time left to this attempt before it is cancelled, if any limit applies. This is meant to be
+    /// propagated downstream, e.g. as a gRPC timeout.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline.map(|d| d.saturating_duration_since(Instant::now()))
+    }
+}
+
+/// Result of a successful call, along with the service that served it. See
+/// [`RoundRobin::run_with_outcome`].
+#[derive(Clone, Debug)]
+pub struct Outcome<T, SvcSrc> {
+    /// Value returned by the run function.
+    pub value: T,
+    /// Index of the service in the round-robin's source list.
+    pub index: usize,
+    /// Source of the service.
+    pub source: SvcSrc,
+    /// Number of attempts used, the successful one included.
+    pub attempts: usize,
+    /// Time elapsed since the start of the call.
+    pub latency: Duration,
+}
+
+/// Snapshot of the state of a [`RoundRobin`], published on every change. See
+/// [`RoundRobin::subscribe`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Status {
+    /// Index of the active service in the source list.
+    pub index: usize,
+    /// Wether the active service is connected.
+    pub connected: bool,
+    /// Connection generation, incremented on every new connection.
+    pub generation: usize,
+    /// Last connection or next error, if any.
+    pub last_error: Option<String>,
+}
+
 /// Round Robin manager.
 ///
 /// This holds a list of services, a way to connect to said services, and a way to run stuff against
@@ -172,20 +487,65 @@ where
 {
     /// Sources used to connect to a service. Usually some form of URL to attempt a connection,
     /// e.g. `amqp://localhost:5672`
-    sources: Vec<SvcSrc>,
+    sources: Arc<Sources<SvcSrc>>,
 
     /// Async connection handler.
     connector: Conn,
 
-    /// How many services to try before giving up. Defaults to the service count.
-    max_attempts: usize,
+    /// How many services to try before giving up. Defaults to the service count, plus one.
+    max_attempts: Option<usize>,
+
+    /// Delay policy applied between two attempts.
+    backoff: Arc<dyn Backoff>,
+
+    /// Delay policy applied each time all services were tried, on top of `backoff`.
+    rotation_backoff: Arc<dyn Backoff>,
+
+    /// Maximum time allowed to the connector to yield a service.
+    connect_timeout: Option<Duration>,
+
+    /// Maximum time allowed to a single run attempt.
+    run_timeout: Option<Duration>,
+
+    /// Circuit breaker configuration, if enabled.
+    circuit_breaker: Option<CircuitBreaker>,
 
-    /// Already connected service handler. We use Arc here to be able to easily clone the service
-    /// handler and avoid issues with references, as they don't play nicely with futures.
-    service: RwLock<Option<Arc<Svc>>>,
+    /// Background health checking task, if enabled.
+    health_task: Option<Task>,
 
-    /// Current service source being connected
-    current: AtomicUsize,
+    /// Background source discovery task, if enabled.
+    discovery_task: Option<Task>,
+
+    /// How often to try moving back to a preferred tier, if enabled.
+    failback: Option<Duration>,
+
+    /// How long calls wait for sources to be added while the list is empty, if at all.
+    wait_for_sources: Option<Duration>,
+
+    /// Last time a failback was attempted.
+    last_failback: std::sync::Mutex<Option<Instant>>,
+
+    /// Connection to the current service.
+    slot: Slot<Svc, SvcSrc, E>,
+
+    /// Observer of state changes, if any.
+    observer: Option<Box<dyn Observer<SvcSrc, E>>>,
+
+    /// Logging configuration.
+    logging: Logging,
+
+    /// Representation of the sources in logs, spans, errors and statistics.
+    redact: Redact<SvcSrc>,
+
+    /// Rate limiter of the exhausted calls logged.
+    exhausted_log: Limiter,
+
+    /// Current state, published to subscribers.
+    status: watch::Sender<Status>,
+
+    /// Name of the round-robin, used to label metrics.
+    #[cfg(feature = "metrics")]
+    name: String,
 
     _phantom: PhantomData<E>,
 }
@@ -193,7 +553,7 @@ where
 impl<SvcSrc, Svc, E, Conn> RoundRobin<SvcSrc, Svc, E, Conn>
 where
     SvcSrc: Debug,
-    E: Next + Display,
+    E: Classify + Display,
     Conn: Connector<SvcSrc, Svc, E>,
 {
     /// Build a new round-robin manager.
@@ -230,182 +590,1360 @@ where
     /// # }
     /// ```
     pub fn new(sources: Vec<SvcSrc>, connector: Conn) -> Self {
+        Self::tiered(vec![sources], connector)
+    }
+
+    /// Build a new round-robin manager from sources grouped in priority tiers, the first tier
+    /// being the preferred one.
+    ///
+    /// Services are tried tier after tier, so that services of a tier are only used once all
+    /// services of the preceding tiers failed. Use [`failback`](Self::failback) to move back to a
+    /// preferred tier once it recovered.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use std::{io::Error, time::Duration};
+    /// # use tourniquet::{async_trait, Connector, RoundRobin};
+    /// #
+    /// # struct Conn;
+    /// #
+    /// # #[async_trait]
+    /// # impl Connector<&'static str, (), Error> for Conn {
+    /// #     async fn connect(&self, src: &&'static str) -> Result<(), Error> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// let rr = RoundRobin::tiered(
+    ///     vec![vec!["local-1", "local-2"], vec!["remote-1", "remote-2"]],
+    ///     Conn,
+    /// )
+    /// .failback(Duration::from_secs(30));
+    /// ```
+    pub fn tiered(tiers: Vec<Vec<SvcSrc>>, connector: Conn) -> Self {
         Self {
-            max_attempts: sources.len() + 1,
-            sources,
+            max_attempts: None,
+            sources: Arc::new(Sources::tiered(tiers)),
             connector,
-            service: RwLock::new(None),
-            current: AtomicUsize::new(0),
+            backoff: Arc::new(NoBackoff),
+            rotation_backoff: Arc::new(NoBackoff),
+            connect_timeout: None,
+            run_timeout: None,
+            circuit_breaker: None,
+            health_task: None,
+            discovery_task: None,
+            failback: None,
+            wait_for_sources: None,
+            last_failback: std::sync::Mutex::new(None),
+            slot: Slot::new(),
+            observer: None,
+            logging: Logging::default(),
+            redact: redact_passwords,
+            exhausted_log: Limiter::default(),
+            status: watch::Sender::new(Status::default()),
+            #[cfg(feature = "metrics")]
+            name: String::new(),
             _phantom: PhantomData,
         }
     }
 
+    /// Build a new round-robin manager with a [`RoundRobinBuilder`], validating its configuration
+    /// rather than panicking on the first call.
+    pub fn builder() -> RoundRobinBuilder<SvcSrc, Svc, E, Conn> {
+        RoundRobinBuilder::new()
+    }
+
     /// Set how many times we will try the next service in case of failure.
     pub fn set_max_attempts(&mut self, count: usize) {
-        self.max_attempts = count;
+        self.max_attempts = Some(count);
     }
 
     /// Set how many times we will try the next service in case of failure.
     pub fn max_attempts(self, count: usize) -> Self {
-        Self { max_attempts: count, ..self }
+        Self { max_attempts: Some(count), ..self }
     }
 
-    #[cfg_attr(
-        feature = "tracing",
-        tracing::instrument(skip(self, run), err, fields(service = Empty, index = Empty)),
-    )]
-    async fn run_inner<Run, RunFut, T>(&self, run: &Run, current: usize) -> Result<T, E>
-    where
-        Run: Fn(Arc<Svc>) -> RunFut,
-        RunFut: Future<Output = Result<T, E>>,
-    {
-        let index = current % self.sources.len();
-
-        #[cfg(feature = "tracing")]
-        {
-            let span = Span::current();
-            span.record("index", &display(index));
-            span.record("service", &debug(&self.sources[index]));
-        }
-
-        // Connect if not already connected
-        if self.service.read().await.is_none() {
-            *self.service.write().await =
-                Some(Arc::new(self.connector.connect(&self.sources[index]).await?));
-        }
+    /// Set the delay policy applied between two attempts. Defaults to no delay at all.
+    pub fn set_backoff(&mut self, backoff: impl Backoff + 'static) {
+        self.backoff = Arc::new(backoff);
+    }
 
-        // Run
-        let fut = run(self.service.read().await.clone().unwrap());
-        #[cfg(feature = "tracing")]
-        let fut = fut.instrument(tracing::debug_span!("run_fn"));
-        let res = fut.await;
+    /// Set the delay policy applied between two attempts. Defaults to no delay at all.
+    pub fn backoff(self, backoff: impl Backoff + 'static) -> Self {
+        Self { backoff: Arc::new(backoff), ..self }
+    }
 
-        if let Err(ref e) = res {
-            // Trash handler only if that's a next error and if we didn't already move to the next
-            // provider (e.g. in another concurrent task).
-            if e.is_next() && current == self.current.load(Ordering::Relaxed) {
-                *self.service.write().await = None;
-            }
-        }
+    /// Set the delay policy applied each time all services were tried and failed, on top of the
+    /// regular backoff. Defaults to no delay at all.
+    pub fn set_rotation_backoff(&mut self, backoff: impl Backoff + 'static) {
+        self.rotation_backoff = Arc::new(backoff);
+    }
 
-        res
+    /// Set the delay policy applied each time all services were tried and failed, on top of the
+    /// regular backoff. Defaults to no delay at all.
+    pub fn rotation_backoff(self, backoff: impl Backoff + 'static) -> Self {
+        Self { rotation_backoff: Arc::new(backoff), ..self }
     }
 
-    /// Run the provided async function against an established service connection.
-    ///
-    /// The connection to the service will be established at this point if not already established.
-    #[cfg_attr(feature = "tracing", instrument(skip(self, run), err))]
-    pub async fn run<R, Fut, T>(&self, run: R) -> Result<T, E>
-    where
-        R: Fn(Arc<Svc>) -> Fut,
-        Fut: Future<Output = Result<T, E>>,
-    {
-        let n_svc = self.sources.len();
-        let mut attempts = 0usize;
+    /// Set the maximum time a connection to a service may take. Should it time out, the next
+    /// service is tried, and [`Error::ConnectTimeout`] is returned if it was the last attempt.
+    pub fn set_connect_timeout(&mut self, timeout: Duration) {
+        self.connect_timeout = Some(timeout);
+    }
 
-        loop {
-            let current = self.current.load(Ordering::Relaxed);
-
-            match self.run_inner(&run, current).await {
-                Ok(t) => return Ok(t),
-                Err(e) => {
-                    if e.is_next() {
-                        log::error!("Service {}/{} failed: {}", current % n_svc, n_svc, e);
-
-                        self.current.fetch_add(1, Ordering::Relaxed);
-                        attempts += 1;
-                        if attempts < self.max_attempts {
-                            continue;
-                        }
-                    }
+    /// Set the maximum time a connection to a service may take. Should it time out, the next
+    /// service is tried, and [`Error::ConnectTimeout`] is returned if it was the last attempt.
+    pub fn connect_timeout(self, timeout: Duration) -> Self {
+        Self { connect_timeout: Some(timeout), ..self }
+    }
 
-                    return Err(e);
-                }
-            }
-        }
+    /// Set the maximum time a single run attempt may take. Should it time out, the service is
+    /// considered unhealthy and the next one is tried, and [`Error::RunTimeout`] is returned if it
+    /// was the last attempt.
+    pub fn set_run_timeout(&mut self, timeout: Duration) {
+        self.run_timeout = Some(timeout);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
-    };
+    /// Set the maximum time a single run attempt may take. Should it time out, the service is
+    /// considered unhealthy and the next one is tried, and [`Error::RunTimeout`] is returned if it
+    /// was the last attempt.
+    pub fn run_timeout(self, timeout: Duration) -> Self {
+        Self { run_timeout: Some(timeout), ..self }
+    }
 
-    #[derive(Debug, PartialEq)]
-    enum Error {
-        Timeout,
-        NotFound,
+    /// Enable per-service circuit breakers: services failing too often are skipped for a while.
+    /// Disabled by default.
+    pub fn set_circuit_breaker(&mut self, breaker: CircuitBreaker) {
+        self.circuit_breaker = Some(breaker);
     }
 
-    impl std::fmt::Display for Error {
-        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-            write!(f, "{:?}", self)
-        }
+    /// Enable per-service circuit breakers: services failing too often are skipped for a while.
+    /// Disabled by default.
+    pub fn circuit_breaker(self, breaker: CircuitBreaker) -> Self {
+        Self { circuit_breaker: Some(breaker), ..self }
     }
 
-    impl Next for Error {
-        fn is_next(&self) -> bool {
-            *self == Self::Timeout
-        }
+    /// Periodically try to move back to a service of a preferred tier, see
+    /// [`tiered`](Self::tiered). Disabled by default.
+    ///
+    /// While connected to a service of a fallback tier, a call is picked every `period` to connect
+    /// to the services of the preferred tiers, which are admitted (healthy, with a closed
+    /// circuit). The connection is switched over to the first one that succeeds, and kept as is
+    /// should they all fail.
+    pub fn set_failback(&mut self, period: Duration) {
+        self.failback = Some(period);
     }
 
-    struct Conn {
-        count: Arc<AtomicUsize>,
-        ok_from: i32,
+    /// Periodically try to move back to a service of a preferred tier, see
+    /// [`tiered`](Self::tiered). Disabled by default.
+    ///
+    /// While connected to a service of a fallback tier, a call is picked every `period` to connect
+    /// to the services of the preferred tiers, which are admitted (healthy, with a closed
+    /// circuit). The connection is switched over to the first one that succeeds, and kept as is
+    /// should they all fail.
+    pub fn failback(self, period: Duration) -> Self {
+        Self { failback: Some(period), ..self }
     }
 
-    #[async_trait]
-    impl Connector<i32, i32, Error> for Conn {
-        async fn connect(&self, src: &i32) -> Result<i32, Error> {
-            self.count.fetch_add(1, Ordering::Relaxed);
-            if *src < self.ok_from {
-                Err(Error::Timeout)
-            } else {
-                Ok(*src)
-            }
-        }
+    /// Wait at most `timeout` for sources to be added when the source list is empty, e.g. until
+    /// the [`discovery`](Self::discovery) yields some, rather than failing right away. Calls
+    /// still fail with [`Error::NoSources`] should the list stay empty, and the wait is bounded
+    /// by the deadline of the call, if any. Disabled by default.
+    pub fn set_wait_for_sources(&mut self, timeout: Duration) {
+        self.wait_for_sources = Some(timeout);
     }
 
-    fn build_rr(
-        svcs: Vec<i32>,
-        ok_from: i32,
-    ) -> (RoundRobin<i32, i32, Error, Conn>, Arc<AtomicUsize>) {
-        let count = Arc::new(AtomicUsize::new(0));
-        let cnt = count.clone();
+    /// Wait at most `timeout` for sources to be added when the source list is empty, e.g. until
+    /// the [`discovery`](Self::discovery) yields some, rather than failing right away. Calls
+    /// still fail with [`Error::NoSources`] should the list stay empty, and the wait is bounded
+    /// by the deadline of the call, if any. Disabled by default.
+    pub fn wait_for_sources(self, timeout: Duration) -> Self {
+        Self { wait_for_sources: Some(timeout), ..self }
+    }
 
-        (RoundRobin::new(svcs, Conn { count: cnt, ok_from }), count)
+    /// Circuit state of the service at `index` in the source list, or `None` if there is no such
+    /// service. Circuits are always closed when circuit breakers are disabled.
+    pub fn circuit_state(&self, index: usize) -> Option<CircuitState> {
+        self.sources.snapshot().get(index).map(|s| s.breaker.state())
     }
 
-    #[tokio::test]
-    async fn test_first_called() {
-        let (rr, count_conn) = build_rr(vec![0, 1], 0);
+    /// Start probing all services every `period` in the background, skipping unhealthy ones. The
+    /// probing stops when the round-robin is dropped. Disabled by default.
+    ///
+    /// Services are probed concurrently, and a probe not completing within `period` counts as a
+    /// failed one. Note that an unhealthy service is skipped even if it is currently connected, and
+    /// that [`Error::Unhealthy`] is returned should all services be unhealthy.
+    ///
+    /// # Panics
+    ///
+    /// This must be called from within a Tokio runtime, and panics if `period` is zero.
+    pub fn set_health_check<H>(&mut self, check: H, period: Duration)
+    where
+        SvcSrc: Send + Sync + 'static,
+        H: HealthCheck<SvcSrc> + Send + Sync + 'static,
+    {
+        self.health_task = Some(health::spawn(self.sources.clone(), check, period));
+    }
 
-        let count_run = AtomicUsize::new(0);
+    /// Start probing all services every `period` in the background, skipping unhealthy ones. The
+    /// probing stops when the round-robin is dropped. Disabled by default.
+    ///
+    /// Services are probed concurrently, and a probe not completing within `period` counts as a
+    /// failed one. Note that an unhealthy service is skipped even if it is currently connected, and
+    /// that [`Error::Unhealthy`] is returned should all services be unhealthy.
+    ///
+    /// # Panics
+    ///
+    /// This must be called from within a Tokio runtime, and panics if `period` is zero.
+    pub fn health_check<H>(mut self, check: H, period: Duration) -> Self
+    where
+        SvcSrc: Send + Sync + 'static,
+        H: HealthCheck<SvcSrc> + Send + Sync + 'static,
+    {
+        self.set_health_check(check, period);
+        self
+    }
 
-        rr.run(|_| async { Ok(count_run.fetch_add(1, Ordering::Relaxed)) }).await.unwrap();
+    /// Health of the service at `index` in the source list, as last reported by the health check,
+    /// or `None` if there is no such service. Services are always healthy when health checking is
+    /// disabled.
+    pub fn is_healthy(&self, index: usize) -> Option<bool> {
+        self.sources.snapshot().get(index).map(|s| s.healthy.load(Ordering::Relaxed))
+    }
 
-        // Async blocks should be called only once
-        assert_eq!(count_conn.load(Ordering::Relaxed), 1);
-        assert_eq!(count_run.load(Ordering::Relaxed), 1);
+    /// Current list of sources.
+    pub fn sources(&self) -> Vec<SvcSrc>
+    where
+        SvcSrc: Clone,
+    {
+        self.sources.snapshot().iter().map(|s| s.src.clone()).collect()
+    }
 
-        rr.run(|_| async { Ok(count_run.fetch_add(1, Ordering::Relaxed)) }).await.unwrap();
+    /// Append a source to the list, in the first tier.
+    pub fn add_source(&self, src: SvcSrc) {
+        self.add_source_in_tier(src, 0);
+    }
 
-        // Connector should not have been called a second time, though the run block should.
-        assert_eq!(count_conn.load(Ordering::Relaxed), 1);
-        assert_eq!(count_run.load(Ordering::Relaxed), 2);
+    /// Append a source to the given tier, see [`tiered`](Self::tiered).
+    pub fn add_source_in_tier(&self, src: SvcSrc, tier: usize) {
+        let source = Source::in_tier(src, tier);
+        self.sources.update(|sources| sources.iter().cloned().chain([source]).collect());
     }
 
-    #[tokio::test]
-    async fn test_exhausted_run() {
-        let (mut rr, count_conn) = build_rr(vec![0, 1], 0);
+    /// Remove a source from the list, returning wether it was found.
+    ///
+    /// Should the service be currently connected, calls already running against it are left to
+    /// complete, and the next call will connect to the next service in the list. See
+    /// [`run`](Self::run) about calls made once the list is empty.
+    pub fn remove_source(&self, src: &SvcSrc) -> bool
+    where
+        SvcSrc: PartialEq,
+    {
+        let mut found = false;
+        self.sources.update(|sources| {
+            let kept: Vec<_> = sources.iter().filter(|s| s.src != *src).cloned().collect();
+            found = kept.len() < sources.len();
+            kept
+        });
+        found
+    }
 
-        let count = AtomicUsize::new(0);
+    /// Atomically replace the whole source list.
+    ///
+    /// Sources present in both lists keep their state (connection, circuit, health) and tier, new
+    /// ones are put in the first tier. Should the
+    /// connected service be removed, calls already running against it are left to complete, and
+    /// the next call will connect to the service following it in the old list, if kept, or to the
+    /// first service of the new list.
+    pub fn replace_sources(&self, sources: Vec<SvcSrc>)
+    where
+        SvcSrc: PartialEq,
+    {
+        self.sources.replace(sources);
+    }
 
-        // With a single attemt, options will be exhausted
-        rr.set_max_attempts(1);
+    /// Keep the source list up to date with `discovery`, e.g. a
+    /// [`DnsDiscovery`](discovery::DnsDiscovery), in the background. The discovery stops when the
+    /// round-robin is dropped.
+    ///
+    /// Sources are discovered again once they expire, or right away when a call failed on all
+    /// attempts. The list is replaced as with [`replace_sources`](Self::replace_sources), and kept
+    /// as is should the discovery fail or yield no source. The current list is used until the
+    /// first discovery completes.
+    ///
+    /// # Panics
+    ///
+    /// This must be called from within a Tokio runtime.
+    pub fn set_discovery<D>(&mut self, discovery: D)
+    where
+        SvcSrc: PartialEq + Send + Sync + 'static,
+        D: Discover<SvcSrc> + 'static,
+    {
+        self.discovery_task = Some(discovery::spawn(self.sources.clone(), discovery));
+    }
+
+    /// Keep the source list up to date with `discovery`, e.g. a
+    /// [`DnsDiscovery`](discovery::DnsDiscovery), in the background. The discovery stops when the
+    /// round-robin is dropped.
+    ///
+    /// Sources are discovered again once they expire, or right away when a call failed on all
+    /// attempts. The list is replaced as with [`replace_sources`](Self::replace_sources), and kept
+    /// as is should the discovery fail or yield no source. The current list is used until the
+    /// first discovery completes.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use std::{io::Error, time::Duration};
+    /// # use tourniquet::{async_trait, Connector, RoundRobin};
+    /// use tourniquet::discovery::{Discover, DnsDiscovery, SystemResolver};
+    /// #
+    /// # struct Conn;
+    /// #
+    /// # #[async_trait]
+    /// # impl Connector<String, String, Error> for Conn {
+    /// #     async fn connect(&self, src: &String) -> Result<String, Error> {
+    /// #         Ok(src.clone())
+    /// #     }
+    /// # }
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let resolver = SystemResolver::new(Duration::from_secs(30));
+    /// let discovery = DnsDiscovery::ip(resolver, "localhost", 5672)
+    ///     .map(|ep| format!("amqp://{}", ep));
+    /// let rr = RoundRobin::new(vec!["amqp://localhost:5672".into()], Conn).discovery(discovery);
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// This must be called from within a Tokio runtime.
+    pub fn discovery<D>(mut self, discovery: D) -> Self
+    where
+        SvcSrc: PartialEq + Send + Sync + 'static,
+        D: Discover<SvcSrc> + 'static,
+    {
+        self.set_discovery(discovery);
+        self
+    }
+
+    /// Set an observer, notified of connections, failures and failovers.
+    pub fn set_observer(&mut self, observer: impl Observer<SvcSrc, E> + 'static) {
+        self.observer = Some(Box::new(observer));
+    }
+
+    /// Set an observer, notified of connections, failures and failovers.
+    pub fn observer(self, observer: impl Observer<SvcSrc, E> + 'static) -> Self {
+        Self { observer: Some(Box::new(observer)), ..self }
+    }
+
+    /// Set how failures are logged, see [`Logging`], including the events of the health check,
+    /// failback and discovery. By default, every failed attempt is logged as an error.
+    pub fn set_logging(&mut self, logging: Logging) {
+        self.logging = logging;
+        self.sources.set_logging(logging, self.redact);
+    }
+
+    /// Set how failures are logged, see [`Logging`], including the events of the health check,
+    /// failback and discovery. By default, every failed attempt is logged as an error.
+    pub fn logging(mut self, logging: Logging) -> Self {
+        self.set_logging(logging);
+        self
+    }
+
+    /// Set how sources are represented in logs, spans, errors and statistics, e.g. to leave out
+    /// secrets other than the passwords of URLs. Defaults to [`redact_passwords`].
+    pub fn set_redact(&mut self, redact: Redact<SvcSrc>) {
+        self.redact = redact;
+        self.sources.set_logging(self.logging, redact);
+    }
+
+    /// Set how sources are represented in logs, spans, errors and statistics, e.g. to leave out
+    /// secrets other than the passwords of URLs. Defaults to [`redact_passwords`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use std::io::Error;
+    /// # use tourniquet::{async_trait, Connector, RoundRobin};
+    /// #
+    /// # struct Conn;
+    /// #
+    /// # #[async_trait]
+    /// # impl Connector<Database, (), Error> for Conn {
+    /// #     async fn connect(&self, src: &Database) -> Result<(), Error> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// #[derive(Debug)]
+    /// struct Database {
+    ///     host: String,
+    ///     token: String,
+    /// }
+    ///
+    /// let db = Database { host: "db01".into(), token: "secret".into() };
+    /// let rr = RoundRobin::new(vec![db], Conn).redact(|db, f| f.write_str(&db.host));
+    /// ```
+    pub fn redact(mut self, redact: Redact<SvcSrc>) -> Self {
+        self.set_redact(redact);
+        self
+    }
+
+    /// Representation of a source, see [`redact`](Self::redact).
+    fn redacted<'a>(&self, src: &'a SvcSrc) -> Redacted<'a, SvcSrc> {
+        Redacted::new(src, self.redact)
+    }
+
+    /// Notify the observer, if any.
+    fn notify(&self, f: impl FnOnce(&dyn Observer<SvcSrc, E>)) {
+        if let Some(observer) = &self.observer {
+            f(observer.as_ref());
+        }
+    }
+
+    /// Set the name of the round-robin, labelling its metrics.
+    ///
+    /// With the `metrics` feature, metrics are recorded through the [`metrics`] facade, labelled
+    /// with this name and the index of the service in the source list:
+    ///
+    /// * `tourniquet_attempts_total`: attempts, labelled with their `result` (`ok` or `error`)
+    /// * `tourniquet_failovers_total`: moves on from a service to the next one
+    /// * `tourniquet_connect_duration_seconds`: latency of the connection attempts
+    /// * `tourniquet_run_duration_seconds`: latency of the calls
+    /// * `tourniquet_exhausted_total`: calls giving up after all attempts failed, only labelled
+    ///   with the name
+    #[cfg(feature = "metrics")]
+    pub fn set_name(&mut self, name: impl Into<String>) {
+        self.name = name.into();
+    }
+
+    /// Set the name of the round-robin, labelling its metrics. See [`set_name`](Self::set_name).
+    #[cfg(feature = "metrics")]
+    pub fn name(self, name: impl Into<String>) -> Self {
+        Self { name: name.into(), ..self }
+    }
+
+    /// Subscribe to the state changes, e.g. to await a failover.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use std::io::Error;
+    /// # use tourniquet::{async_trait, Connector, RoundRobin};
+    /// #
+    /// # struct Conn;
+    /// #
+    /// # #[async_trait]
+    /// # impl Connector<u16, u16, Error> for Conn {
+    /// #     async fn connect(&self, src: &u16) -> Result<u16, Error> {
+    /// #         Ok(*src)
+    /// #     }
+    /// # }
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let rr = RoundRobin::new(vec![1, 2], Conn);
+    /// let mut status = rr.subscribe();
+    ///
+    /// rr.run(|n| async move { Ok(*n) }).await.unwrap();
+    /// let status = status.wait_for(|s| s.connected).await.unwrap();
+    /// assert_eq!(status.generation, 1);
+    /// # }
+    /// ```
+    pub fn subscribe(&self) -> watch::Receiver<Status> {
+        self.status.subscribe()
+    }
+
+    /// Current state.
+    pub fn status(&self) -> Status {
+        self.status.borrow().clone()
+    }
+
+    /// Statistics of every service, in the order of the source list.
+    pub fn stats(&self) -> Vec<SourceStats> {
+        let sources = self.sources.snapshot();
+        let stats = sources.iter().enumerate();
+        stats.map(|(i, s)| s.stats.snapshot(i, self.redacted(&s.src).to_string())).collect()
+    }
+
+    /// The service at `index` was connected to.
+    fn connected(&self, index: usize, source: &Source<SvcSrc>) {
+        self.notify(|o| o.on_connected(index, &source.src));
+        self.status.send_modify(|s| {
+            s.index = index;
+            s.connected = true;
+            s.generation += 1;
+        });
+    }
+
+    /// The connection to the service at `index` failed.
+    fn connect_failed(&self, index: usize, source: &Source<SvcSrc>, error: &RunError<E>) {
+        self.notify(|o| o.on_connect_error(index, &source.src, error));
+        self.status.send_modify(|s| s.last_error = Some(error.to_string()));
+    }
+
+    /// A call against the service at `index` failed with a next error.
+    fn next_failed(&self, index: usize, source: &Source<SvcSrc>, error: &RunError<E>) {
+        self.notify(|o| o.on_next_error(index, &source.src, error));
+        self.status.send_modify(|s| s.last_error = Some(error.to_string()));
+    }
+
+    /// Notify the observer of moving on from the service at `from`.
+    fn failed_over(&self, sources: &[Arc<Source<SvcSrc>>], from: usize) {
+        let to = self.sources.current.load(Ordering::Relaxed) % sources.len();
+        sources[from].stats.failed_over();
+        #[cfg(feature = "tracing")]
+        tracing::info!(from, to, service = %self.redacted(&sources[to].src), "failover");
+        #[cfg(feature = "metrics")]
+        telemetry::failover(&self.name, from);
+        self.logging.log_failover((from, &sources[from]), (to, &sources[to]), self.redact);
+        self.notify(|o| o.on_failover(from, &sources[from].src, to, &sources[to].src));
+        self.status.send_modify(|s| s.index = to);
+    }
+
+    /// Give up on a call, after all attempts failed, all services were skipped or there was no
+    /// source.
+    fn exhausted(&self, failures: Failures<E>) -> Exhausted<E> {
+        self.sources.refresh.notify_one();
+        #[cfg(feature = "metrics")]
+        telemetry::exhausted(&self.name);
+        let attempts = failures.count;
+        let errors = self.describe(failures);
+        self.logging.log_exhausted(&self.exhausted_log, attempts, errors.last());
+        self.notify(|o| o.on_exhausted(&errors));
+        errors
+    }
+
+    /// Number of sources, waiting for some to be added while there is none, should
+    /// [`wait_for_sources`](Self::wait_for_sources) be enabled. Zero if the wait timed out.
+    async fn await_sources(&self, deadline: Option<Instant>) -> usize {
+        let timeout = match self.wait_for_sources {
+            Some(timeout) => timeout,
+            None => return self.sources.snapshot().len(),
+        };
+        let deadline = earliest(deadline, Instant::now().checked_add(timeout));
+
+        loop {
+            // Register for changes before looking at the list, so that none is missed
+            let mut changed = pin!(self.sources.changed.notified());
+            changed.as_mut().enable();
+
+            let n_svc = self.sources.snapshot().len();
+            if n_svc > 0 {
+                return n_svc;
+            }
+            match deadline {
+                Some(d) => {
+                    if tokio::time::timeout_at(d, changed).await.is_err() {
+                        return 0;
+                    }
+                }
+                None => changed.await,
+            }
+        }
+    }
+
+    /// Check wether the service may be connected to.
+    fn admit(&self, source: &Source<SvcSrc>) -> Result<(), Error> {
+        source.admit(self.circuit_breaker.as_ref())
+    }
+
+    /// Report the outcome of an attempt to the circuit breaker of the service.
+    fn report(&self, source: &Source<SvcSrc>, failure: Option<&Failure<E>>) {
+        source.report(self.circuit_breaker.as_ref(), failure.is_some_and(Failure::is_next));
+    }
+
+    /// Move back to a service of a preferred tier, should the connected one be in a fallback tier
+    /// and the failback period be elapsed.
+    async fn try_failback(&self) {
+        let period = match self.failback {
+            Some(period) => period,
+            None => return,
+        };
+        let conn = match self.slot.load() {
+            Some(conn) if conn.source.tier > 0 => conn,
+            _ => return,
+        };
+
+        {
+            let now = Instant::now();
+            let mut last = self.last_failback.lock().unwrap();
+            let since = last.map_or(conn.since, |last| last.max(conn.since));
+            if now < since + period {
+                return;
+            }
+            *last = Some(now);
+        }
+
+        // Connect while holding the connecting lock, as any other connection, and give up should
+        // the connection have changed in the meantime
+        let _connecting = self.slot.lock().await;
+        if !self.slot.holds(&conn) {
+            return;
+        }
+
+        let sources = self.sources.snapshot();
+        let preferred = sources.iter().enumerate().take_while(|(_, s)| s.tier < conn.source.tier);
+        for (index, source) in preferred {
+            if self.admit(source).is_err() {
+                continue;
+            }
+
+            self.notify(|o| o.on_connect(index, &source.src));
+            let start = Instant::now();
+            let connect = self.connector.connect(&source.src);
+            let res = with_timeout(self.connect_timeout, connect, Error::ConnectTimeout).await;
+            source.stats.connected(start.elapsed(), res.is_err());
+            #[cfg(feature = "metrics")]
+            telemetry::connect(&self.name, index, start.elapsed());
+            self.report(source, res.as_ref().err());
+            match res {
+                Ok(svc) => {
+                    self.logging.log_failback(index, source, self.redact);
+                    self.slot.store(Arc::new(Connection::new(svc, source)));
+                    self.sources.current.store(index, Ordering::Relaxed);
+                    self.connected(index, source);
+                    return;
+                }
+                Err(f) => {
+                    self.logging.log_failure(index, source, self.redact, &f.error);
+                    self.connect_failed(index, source, &f.error);
+                }
+            }
+        }
+    }
+
+    /// Connect to the service `current` points to, holding the connecting lock.
+    async fn connect(
+        &self,
+        sources: &[Arc<Source<SvcSrc>>],
+        current: usize,
+        connecting: &mut Connecting<'_, Svc, SvcSrc, E>,
+    ) -> Result<Arc<Connection<Svc, SvcSrc>>, Failure<E>> {
+        let index = current % sources.len();
+        let source = &sources[index];
+        if let Err(e) = self.admit(source) {
+            if self.skip(current) {
+                self.failed_over(sources, index);
+            }
+            return Err(Failure::skipped(e).at(index));
+        }
+
+        self.notify(|o| o.on_connect(index, &source.src));
+        let start = Instant::now();
+        let connect = self.connector.connect(&source.src);
+        let res = with_timeout(self.connect_timeout, connect, Error::ConnectTimeout).await;
+        source.stats.connected(start.elapsed(), res.is_err());
+        #[cfg(feature = "metrics")]
+        telemetry::connect(&self.name, index, start.elapsed());
+        match connecting.complete(index, source, res) {
+            Ok(conn) => {
+                self.report(source, None);
+                self.connected(index, source);
+                Ok(conn)
+            }
+            Err(f) => {
+                self.report(source, Some(&f));
+                self.connect_failed(index, source, &f.error);
+                // Move on while still connecting, so that waiting callers try the next service
+                if f.is_next() {
+                    self.sources.current.fetch_add(1, Ordering::Relaxed);
+                    self.failed_over(sources, index);
+                }
+                Err(f)
+            }
+        }
+    }
+
+    /// Move on to the next service after skipping the one `current` points to, unless another
+    /// caller already did. Returns wether it moved on.
+    fn skip(&self, current: usize) -> bool {
+        self.sources
+            .current
+            .compare_exchange(current, current + 1, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Failure of the attempt made against the service at `index`, or the one `index` points to,
+    /// `elapsed` after the start of the call.
+    fn failure(&self, index: usize, elapsed: Duration, error: RunError<E>) -> AttemptError<E> {
+        let sources = self.sources.snapshot();
+        let index = index.checked_rem(sources.len()).unwrap_or(0);
+        let source =
+            sources.get(index).map(|s| self.redacted(&s.src).to_string()).unwrap_or_default();
+        AttemptError { index, source, error, elapsed }
+    }
+
+    /// Record the failure of the attempt made against the service at `index`, `start` being the
+    /// start of the call.
+    fn failed(&self, failures: &mut Failures<E>, index: usize, start: Instant, error: RunError<E>) {
+        failures.count += 1;
+        match &mut failures.all {
+            Some(all) => all.push(self.failure(index, start.elapsed(), error)),
+            None => failures.last = Some((index, error, start.elapsed())),
+        }
+    }
+
+    /// Describe the failures of a call.
+    fn describe(&self, failures: Failures<E>) -> Exhausted<E> {
+        Exhausted::new(match failures.all {
+            Some(all) => all,
+            None => failures
+                .last
+                .map(|(i, e, elapsed)| self.failure(i, elapsed, e))
+                .into_iter()
+                .collect(),
+        })
+    }
+
+    /// Drop the connection to the service, unless it was already replaced. With `next`, move on to
+    /// the next service as well: only the first caller dropping a given connection does. Returns
+    /// wether it moved on.
+    async fn disconnect(&self, conn: &Arc<Connection<Svc, SvcSrc>>, next: bool) -> bool {
+        if !next {
+            if self.slot.disconnect(conn) {
+                self.status.send_modify(|s| s.connected = false);
+            }
+            return false;
+        }
+
+        // Move on while holding the connecting lock, so that reconnecting callers see it
+        if self.slot.holds(conn) {
+            let _connecting = self.slot.lock().await;
+            if self.slot.disconnect(conn) {
+                self.sources.current.fetch_add(1, Ordering::Relaxed);
+                self.status.send_modify(|s| s.connected = false);
+                return true;
+            }
+        }
+        false
+    }
+
+    async fn run_inner<Run, RunFut, T>(
+        &self,
+        run: &Run,
+        mut current: usize,
+        number: usize,
+        deadline: Option<Instant>,
+    ) -> Result<Served<T, SvcSrc>, Failure<E>>
+    where
+        Run: Fn(Arc<Svc>, &SvcSrc, Attempt) -> RunFut,
+        RunFut: Future<Output = Result<T, E>>,
+    {
+        let sources = self.sources.snapshot();
+        if sources.is_empty() {
+            return Err(Failure::fatal(Error::NoSources, 0));
+        }
+        let mut index = current % sources.len();
+
+        #[cfg(feature = "tracing")]
+        {
+            let span = Span::current();
+            span.record("index", display(index));
+            span.record("service", display(self.redacted(&sources[index].src)));
+        }
+
+        let mut conn = self.slot.load();
+
+        // Drain connections to removed sources: calls already running against it complete, and
+        // new ones reconnect.
+        if let Some(c) = conn.as_ref().filter(|c| c.source.removed.load(Ordering::Relaxed)) {
+            self.disconnect(c, false).await;
+            conn = None;
+        }
+
+        // Skip the service if known to be unhealthy, even if already connected
+        if let Some(c) = conn.as_ref().filter(|c| !c.source.healthy.load(Ordering::Relaxed)) {
+            let from = sources.iter().position(|s| Arc::ptr_eq(s, &c.source)).unwrap_or(index);
+            if self.disconnect(c, true).await {
+                self.failed_over(&sources, from);
+            }
+            return Err(Failure::skipped(Error::Unhealthy).at(from));
+        }
+
+        // Connect if not already connected. Concurrent callers wait for the connection in
+        // progress, if any, rather than connecting on their own.
+        let conn = match conn {
+            Some(conn) => conn,
+            None => match self.slot.acquire().await {
+                Acquired::Connected(conn) => conn,
+                Acquired::Failed(f) => return Err(f),
+                Acquired::Connect(mut connecting) => {
+                    // Should the connection in progress have failed over, move on along with it
+                    current = self.sources.current.load(Ordering::Relaxed);
+                    index = current % sources.len();
+                    #[cfg(feature = "tracing")]
+                    {
+                        let span = Span::current();
+                        span.record("index", display(index));
+                        span.record("service", display(self.redacted(&sources[index].src)));
+                        span.record("reconnect", true);
+                    }
+                    self.connect(&sources, current, &mut connecting).await?
+                }
+            },
+        };
+
+        // The connected service may not be the current one, e.g. after a concurrent failover
+        if !Arc::ptr_eq(&conn.source, &sources[index]) {
+            index = sources.iter().position(|s| Arc::ptr_eq(s, &conn.source)).unwrap_or(index);
+            #[cfg(feature = "tracing")]
+            {
+                let span = Span::current();
+                span.record("index", display(index));
+                span.record("service", display(self.redacted(&conn.source.src)));
+            }
+        }
+
+        // Run
+        let run_deadline = self.run_timeout.and_then(|t| Instant::now().checked_add(t));
+        let deadline = earliest(deadline, run_deadline);
+        let attempt = Attempt { index, number, deadline };
+        let fut = run(conn.svc.clone(), &conn.source.src, attempt);
+        #[cfg(feature = "tracing")]
+        let fut = fut.instrument(tracing::debug_span!("run_fn"));
+        let start = Instant::now();
+        let res = with_timeout(self.run_timeout, fut, Error::RunTimeout).await;
+        let res = res.map_err(|f| f.at(index));
+        conn.source.stats.ran(start.elapsed(), res.is_err());
+        #[cfg(feature = "metrics")]
+        telemetry::run(&self.name, index, start.elapsed());
+        self.report(&conn.source, res.as_ref().err());
+
+        // Concurrent calls failing on the same connection move on to the next service only once,
+        // the others retrying on the new one.
+        match res.as_ref().map_err(|f| (f.class, &f.error)) {
+            Err((Classification::Next(_), e)) => {
+                self.next_failed(index, &conn.source, e);
+                if self.disconnect(&conn, true).await {
+                    self.failed_over(&sources, index);
+                }
+            }
+            Err((Classification::Reconnect(_), _)) => {
+                self.disconnect(&conn, false).await;
+            }
+            _ => (),
+        }
+
+        res.map(|t| (t, index, conn.source.clone()))
+    }
+
+    /// Run the provided async function against an established service connection.
+    ///
+    /// The connection to the service will be established at this point if not already established.
+    ///
+    /// Tourniquet's own errors, like [`Error::NoSources`] should the source list be empty, are
+    /// converted into `E`. See [`run_with_errors`](Self::run_with_errors) for error types that
+    /// are not convertible.
+    pub async fn run<R, Fut, T>(&self, run: R) -> Result<T, E>
+    where
+        E: From<Error> + Send + Sync + 'static,
+        R: Fn(Arc<Svc>) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        self.run_attempts(RunOptions::default(), false, |svc, _, _| run(svc))
+            .await
+            .map(|outcome| outcome.value)
+            .map_err(|e| e.into_last().into_error())
+    }
+
+    /// Same as [`run`](Self::run), with per-call options overriding the round-robin's ones.
+    pub async fn run_with<R, Fut, T>(&self, opts: RunOptions, run: R) -> Result<T, E>
+    where
+        E: From<Error> + Send + Sync + 'static,
+        R: Fn(Arc<Svc>) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        self.run_attempts(opts, false, |svc, _, _| run(svc))
+            .await
+            .map(|outcome| outcome.value)
+            .map_err(|e| e.into_last().into_error())
+    }
+
+    /// Same as [`run`](Self::run), giving the source of the service and its index in the source
+    /// list to the run function, e.g. to build absolute URLs or tag metrics.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use std::io::Error;
+    /// # use tourniquet::{async_trait, Connector, RoundRobin};
+    /// #
+    /// # struct Conn;
+    /// #
+    /// # #[async_trait]
+    /// # impl Connector<String, (), Error> for Conn {
+    /// #     async fn connect(&self, src: &String) -> Result<(), Error> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let rr = RoundRobin::new(vec!["https://a.example".into(), "https://b.example".into()], Conn);
+    ///
+    /// let url = rr
+    ///     .run_with_source(|_svc, src, _index| {
+    ///         let url = format!("{}/users/42", src);
+    ///         async move { Ok(url) }
+    ///     })
+    ///     .await
+    ///     .unwrap();
+    ///
+    /// assert_eq!(url, "https://a.example/users/42");
+    /// # }
+    /// ```
+    pub async fn run_with_source<R, Fut, T>(&self, run: R) -> Result<T, E>
+    where
+        E: From<Error> + Send + Sync + 'static,
+        R: Fn(Arc<Svc>, &SvcSrc, usize) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let run = |svc, src: &_, attempt: Attempt| run(svc, src, attempt.index());
+        let outcome = self.run_attempts(RunOptions::default(), false, run).await;
+        outcome.map(|outcome| outcome.value).map_err(|e| e.into_last().into_error())
+    }
+
+    /// Same as [`run_with`](Self::run_with), giving information about the current attempt to the
+    /// run function, like the time left before its cancellation.
+    ///
+    /// Tourniquet's own errors, like an exceeded deadline, are reported as
+    /// [`RunError::Tourniquet`], so that `E` does not need to be convertible from [`Error`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use std::{io::Error, time::Duration};
+    /// # use tourniquet::{async_trait, Connector, RoundRobin, RunOptions};
+    /// #
+    /// # struct Conn;
+    /// #
+    /// # #[async_trait]
+    /// # impl Connector<u16, u16, Error> for Conn {
+    /// #     async fn connect(&self, src: &u16) -> Result<u16, Error> {
+    /// #         Ok(*src)
+    /// #     }
+    /// # }
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let rr = RoundRobin::new(vec![1, 2], Conn);
+    /// let opts = RunOptions::new().timeout(Duration::from_secs(5));
+    ///
+    /// rr.run_with_attempt(opts, |svc, attempt| async move {
+    ///     let timeout = attempt.remaining().unwrap();
+    ///     // Send the request to the service with the timeout
+    ///     Ok(())
+    /// })
+    /// .await
+    /// .unwrap();
+    /// # }
+    /// ```
+    pub async fn run_with_attempt<R, Fut, T>(
+        &self,
+        opts: RunOptions,
+        run: R,
+    ) -> Result<T, RunError<E>>
+    where
+        R: Fn(Arc<Svc>, Attempt) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        self.run_attempts(opts, false, |svc, _, attempt| run(svc, attempt))
+            .await
+            .map(|outcome| outcome.value)
+            .map_err(Exhausted::into_last)
+    }
+
+    /// Same as [`run_with_attempt`](Self::run_with_attempt), returning the service that served
+    /// the call along with the value. The index is the same as the one recorded in the attempt
+    /// spans, see [Tracing](crate#tracing).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use std::io::Error;
+    /// # use tourniquet::{async_trait, Connector, RoundRobin, RunOptions};
+    /// #
+    /// # struct Conn;
+    /// #
+    /// # #[async_trait]
+    /// # impl Connector<u16, u16, Error> for Conn {
+    /// #     async fn connect(&self, src: &u16) -> Result<u16, Error> {
+    /// #         Ok(*src)
+    /// #     }
+    /// # }
+    /// #
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let rr = RoundRobin::new(vec![1, 2], Conn);
+    /// let outcome = rr.run_with_outcome(RunOptions::new(), |svc, _| async move { Ok(*svc) }).await;
+    /// let outcome = outcome.unwrap();
+    ///
+    /// assert_eq!((outcome.value, outcome.index, outcome.source), (1, 0, 1));
+    /// # }
+    /// ```
+    pub async fn run_with_outcome<R, Fut, T>(
+        &self,
+        opts: RunOptions,
+        run: R,
+    ) -> Result<Outcome<T, SvcSrc>, RunError<E>>
+    where
+        SvcSrc: Clone,
+        R: Fn(Arc<Svc>, Attempt) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let Outcome { value, index, source, attempts, latency } = self
+            .run_attempts(opts, false, |svc, _, attempt| run(svc, attempt))
+            .await
+            .map_err(Exhausted::into_last)?;
+        Ok(Outcome { value, index, source: source.src.clone(), attempts, latency })
+    }
+
+    /// Same as [`run_with_attempt`](Self::run_with_attempt), returning the errors of all attempts
+    /// should the call fail, rather than only the last one. See [`Exhausted`].
+    ///
+    /// The errors are only collected by this function, the other ones only keeping the last one.
+    /// Tourniquet's own errors are reported as [`RunError::Tourniquet`], so that `E` does not need
+    /// to be convertible from [`Error`].
+    pub async fn run_with_errors<R, Fut, T>(
+        &self,
+        opts: RunOptions,
+        run: R,
+    ) -> Result<T, Exhausted<E>>
+    where
+        R: Fn(Arc<Svc>, Attempt) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        self.run_attempts(opts, true, |svc, _, attempt| run(svc, attempt))
+            .await
+            .map(|outcome| outcome.value)
+    }
+
+    /// Run the provided async function, trying services until it succeeds. The failures of all
+    /// attempts are only collected with `collect` or for the observer, otherwise only the last
+    /// one is returned.
+    #[cfg_attr(feature = "tracing", instrument(skip(self, opts, collect, run), err))]
+    async fn run_attempts<R, Fut, T>(
+        &self,
+        opts: RunOptions,
+        collect: bool,
+        run: R,
+    ) -> Result<Outcome<T, Arc<Source<SvcSrc>>>, Exhausted<E>>
+    where
+        R: Fn(Arc<Svc>, &SvcSrc, Attempt) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        self.try_failback().await;
+
+        let start = Instant::now();
+        let deadline = opts.deadline_from(start);
+        let mut failures = Failures::new(collect || self.observer.is_some());
+        let n_svc = self.await_sources(deadline).await;
+        if n_svc == 0 {
+            self.failed(&mut failures, 0, start, Error::NoSources.into());
+            return Err(self.exhausted(failures));
+        }
+        let max_attempts = self.max_attempts.unwrap_or(n_svc + 1);
+        let backoff = opts.backoff.as_deref().unwrap_or(&*self.backoff);
+        let rotation_backoff = opts.rotation_backoff.as_deref().unwrap_or(&*self.rotation_backoff);
+        let (mut attempts, mut moves) = (0usize, 0usize);
+        let mut skipped_svc = 0usize;
+        let (mut delay, mut rotation_delay) = (Duration::ZERO, Duration::ZERO);
+
+        loop {
+            let current = self.sources.current.load(Ordering::Relaxed);
+
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                self.failed(&mut failures, current, start, Error::DeadlineExceeded.into());
+                return Err(self.exhausted(failures));
+            }
+
+            #[cfg(feature = "tracing")]
+            let span = trace::attempt(attempts + 1);
+            let attempt = self.run_inner(&run, current, attempts + 1, deadline);
+            #[cfg(feature = "tracing")]
+            let attempt = attempt.instrument(span.clone());
+            let mut cancelled = false;
+            let res = match deadline {
+                // The attempt was cancelled, wherever it was made: blame the current service
+                Some(d) => tokio::time::timeout_at(d, attempt).await.unwrap_or_else(|_| {
+                    cancelled = true;
+                    Err(Failure::fatal(Error::DeadlineExceeded, current % n_svc))
+                }),
+                None => attempt.await,
+            };
+            #[cfg(feature = "tracing")]
+            trace::record(&span, &res);
+
+            match res {
+                Ok((value, index, source)) => {
+                    #[cfg(feature = "metrics")]
+                    telemetry::attempt(&self.name, index, true);
+                    let (attempts, latency) = (attempts + 1, start.elapsed());
+                    return Ok(Outcome { value, index, source, attempts, latency });
+                }
+                Err(Failure { error: e, skipped: true, index, .. }) => {
+                    // The service was skipped and moved on from, without it counting as an
+                    // attempt, unless all services are skipped.
+                    skipped_svc += 1;
+                    if skipped_svc >= n_svc {
+                        if failures.count == 0 {
+                            self.failed(&mut failures, index, start, e);
+                        }
+                        return Err(self.exhausted(failures));
+                    }
+                }
+                Err(Failure { error: e, class, index, .. }) => {
+                    skipped_svc = 0;
+                    #[cfg(feature = "metrics")]
+                    telemetry::attempt(&self.name, index, false);
+
+                    if class == Classification::Fatal {
+                        self.failed(&mut failures, index, start, e);
+                        // Unlike a fatal error, the deadline exhausts the attempts of the call
+                        return Err(if cancelled {
+                            self.exhausted(failures)
+                        } else {
+                            self.describe(failures)
+                        });
+                    }
+
+                    if let Some(source) = self.sources.snapshot().get(index) {
+                        self.logging.log_failure(index, source, self.redact, &e);
+                    }
+                    self.failed(&mut failures, index, start, e);
+
+                    // The service was already moved on from by the attempt
+                    let next = matches!(class, Classification::Next(_));
+                    if next {
+                        moves += 1;
+                    }
+                    attempts += 1;
+                    if attempts >= max_attempts {
+                        return Err(self.exhausted(failures));
+                    }
+
+                    delay = backoff.delay(retry_count(attempts), delay);
+                    let mut wait = delay;
+                    if next && moves.is_multiple_of(n_svc) {
+                        rotation_delay =
+                            rotation_backoff.delay(retry_count(moves / n_svc), rotation_delay);
+                        wait = wait.saturating_add(rotation_delay);
+                    }
+                    let wait = wait.max(class.retry_after().unwrap_or_default());
+
+                    if !wait.is_zero() {
+                        // Do not oversleep the deadline
+                        match earliest(Instant::now().checked_add(wait), deadline) {
+                            Some(wake) => tokio::time::sleep_until(wake).await,
+                            None => tokio::time::sleep(wait).await,
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn retry_count(n: usize) -> u32 {
+    n.try_into().unwrap_or(u32::MAX)
+}
+
+fn earliest(a: Option<Instant>, b: Option<Instant>) -> Option<Instant> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Failed attempts of a call. Describing a failure has a cost, so that all of them are only
+/// collected when asked for: otherwise, only the last one is kept, and described once the call
+/// gave up.
+struct Failures<E> {
+    /// Failures of all attempts, should they be collected.
+    all: Option<Vec<AttemptError<E>>>,
+    /// Last failure otherwise: index of the service, error, and time elapsed since the start.
+    last: Option<(usize, RunError<E>, Duration)>,
+    /// Number of failures.
+    count: usize,
+}
+
+impl<E> Failures<E> {
+    fn new(collect: bool) -> Self {
+        Self { all: collect.then(Vec::new), last: None, count: 0 }
+    }
+}
+
+/// Value returned by a successful attempt, along with the index and source of the service.
+type Served<T, SvcSrc> = (T, usize, Arc<Source<SvcSrc>>);
+
+/// Error of a single attempt, along with what to do next.
+struct Failure<E> {
+    error: RunError<E>,
+    class: Classification,
+    /// The service was not even tried, as it is unhealthy or its circuit is open.
+    skipped: bool,
+    /// Index of the service the attempt was made against, see [`at`](Self::at).
+    index: usize,
+}
+
+impl<E> Failure<E> {
+    fn skipped(error: Error) -> Self {
+        Self { error: error.into(), class: Classification::Next(None), skipped: true, index: 0 }
+    }
+
+    /// Failure of the call itself, at the service at `index`.
+    fn fatal(error: Error, index: usize) -> Self {
+        Self { error: error.into(), class: Classification::Fatal, skipped: false, index }
+    }
+
+    /// Set the index of the service the attempt was made against.
+    fn at(self, index: usize) -> Self {
+        Self { index, ..self }
+    }
+
+    fn is_next(&self) -> bool {
+        matches!(self.class, Classification::Next(_))
+    }
+}
+
+impl<E: Classify> From<E> for Failure<E> {
+    fn from(error: E) -> Self {
+        Self { class: error.classify(), error: RunError::Service(error), skipped: false, index: 0 }
+    }
+}
+
+impl<E: Display> Display for Failure<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.error, f)
+    }
+}
+
+/// Await the future for at most `timeout`, if any, failing with `err`. A timeout always mandates
+/// trying the next service.
+async fn with_timeout<F, T, E>(
+    timeout: Option<Duration>,
+    fut: F,
+    err: impl FnOnce(Duration) -> Error,
+) -> Result<T, Failure<E>>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Classify,
+{
+    match timeout {
+        Some(t) => match tokio::time::timeout(t, fut).await {
+            Ok(res) => Ok(res?),
+            Err(_) => Err(Failure {
+                error: err(t).into(),
+                class: Classification::Next(None),
+                skipped: false,
+                index: 0,
+            }),
+        },
+        None => Ok(fut.await?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use backoff::Constant;
+    use breaker::{CircuitBreaker, CircuitState};
+    use std::{
+        io::{Error as IoError, ErrorKind},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+    use tokio::time::Instant;
+
+    #[derive(Debug, PartialEq)]
+    enum Error {
+        Timeout,
+        NotFound,
+        RoundRobin(crate::Error),
+    }
+
+    impl From<crate::Error> for Error {
+        fn from(e: crate::Error) -> Self {
+            Self::RoundRobin(e)
+        }
+    }
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{:?}", self)
+        }
+    }
+
+    impl Next for Error {
+        fn is_next(&self) -> bool {
+            match self {
+                Self::Timeout => true,
+                Self::NotFound => false,
+                Self::RoundRobin(e) => e.is_next(),
+            }
+        }
+    }
+
+    struct Conn {
+        count: Arc<AtomicUsize>,
+        ok_from: i32,
+    }
+
+    #[async_trait]
+    impl Connector<i32, i32, Error> for Conn {
+        async fn connect(&self, src: &i32) -> Result<i32, Error> {
+            self.count.fetch_add(1, Ordering::Relaxed);
+            if *src < self.ok_from {
+                Err(Error::Timeout)
+            } else {
+                Ok(*src)
+            }
+        }
+    }
+
+    fn build_rr(
+        svcs: Vec<i32>,
+        ok_from: i32,
+    ) -> (RoundRobin<i32, i32, Error, Conn>, Arc<AtomicUsize>) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let cnt = count.clone();
+
+        (RoundRobin::new(svcs, Conn { count: cnt, ok_from }), count)
+    }
+
+    #[tokio::test]
+    async fn test_first_called() {
+        let (rr, count_conn) = build_rr(vec![0, 1], 0);
+
+        let count_run = AtomicUsize::new(0);
+
+        rr.run(|_| async { Ok(count_run.fetch_add(1, Ordering::Relaxed)) }).await.unwrap();
+
+        // Async blocks should be called only once
+        assert_eq!(count_conn.load(Ordering::Relaxed), 1);
+        assert_eq!(count_run.load(Ordering::Relaxed), 1);
+
+        rr.run(|_| async { Ok(count_run.fetch_add(1, Ordering::Relaxed)) }).await.unwrap();
+
+        // Connector should not have been called a second time, though the run block should.
+        assert_eq!(count_conn.load(Ordering::Relaxed), 1);
+        assert_eq!(count_run.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_run() {
+        let (mut rr, count_conn) = build_rr(vec![0, 1], 0);
+
+        let count = AtomicUsize::new(0);
+
+        // With a single attemt, options will be exhausted
+        rr.set_max_attempts(1);
 
         let res = rr
             .run(|n| {
@@ -416,82 +1954,1097 @@ mod tests {
                         _ => Ok(n),
                     }
                 }
-            })
-            .await;
+            })
+            .await;
+
+        assert_eq!(count_conn.load(Ordering::Relaxed), 1);
+        match res {
+            Ok(_) => panic!("Run did not error"),
+            Err(Error::Timeout) => (),
+            Err(_) => panic!("Wrong error"),
+        }
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_try_next_run() {
+        let (rr, count_conn) = build_rr(vec![0, 1], 0);
+
+        let count = AtomicUsize::new(0);
+
+        rr.run(|n| {
+            count.fetch_add(1, Ordering::Relaxed);
+            async move {
+                match *n {
+                    0 => Err(Error::Timeout), // Next error
+                    _ => Ok(n),
+                }
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(count_conn.load(Ordering::Relaxed), 2);
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_connector() {
+        let (mut rr, count_conn) = build_rr(vec![0, 1], 1);
+
+        let count = AtomicUsize::new(0);
+
+        // With a single attemt, options will be exhausted
+        rr.set_max_attempts(1);
+
+        let res = rr.run(|_| async { Ok(count.fetch_add(1, Ordering::Relaxed)) }).await;
+
+        assert_eq!(count_conn.load(Ordering::Relaxed), 1);
+        match res {
+            Ok(_) => panic!("Connect did not error"),
+            Err(Error::Timeout) => (),
+            Err(_) => panic!("Wrong error"),
+        }
+        assert_eq!(count.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_try_next_connector() {
+        let (rr, count_conn) = build_rr(vec![0, 1], 1);
+
+        let count = AtomicUsize::new(0);
+
+        rr.run(|_| async { Ok(count.fetch_add(1, Ordering::Relaxed)) }).await.unwrap();
+
+        assert_eq!(count_conn.load(Ordering::Relaxed), 2);
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_backoff() {
+        let (rr, count_conn) = build_rr(vec![0, 1], 2);
+        let rr = rr
+            .max_attempts(5)
+            .backoff(Constant(Duration::from_millis(100)))
+            .rotation_backoff(Constant(Duration::from_secs(1)));
+
+        let start = Instant::now();
+        let res = rr.run(|_| async { Ok(()) }).await;
+
+        assert_eq!(res, Err(Error::Timeout));
+        assert_eq!(count_conn.load(Ordering::Relaxed), 5);
+        // 4 retries, two of them after a full rotation
+        assert_eq!(start.elapsed(), Duration::from_millis(4 * 100 + 2 * 1000));
+
+        // Per-call options take precedence
+        let start = Instant::now();
+        let opts = RunOptions::new().backoff(NoBackoff);
+        let res = rr.run_with(opts, |_| async { Ok(()) }).await;
+
+        assert_eq!(res, Err(Error::Timeout));
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_abort() {
+        let (rr, _) = build_rr(vec![0, 1], 1);
+
+        let res = rr.run(|_| async { Err::<(), _>(Error::NotFound) }).await;
+
+        match res {
+            Ok(_) => panic!("Run did not error"),
+            Err(Error::NotFound) => (),
+            Err(_) => panic!("Connector error aborted"),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_deadline() {
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let exhausted = || {
+            let events: Vec<String> = events.lock().unwrap().drain(..).collect();
+            events.iter().filter(|e| e.starts_with("exhausted")).count()
+        };
+        let (rr, count_conn) = build_rr(vec![0, 1, 2], 3);
+        let rr = rr.max_attempts(10).backoff(Constant(Duration::from_secs(1)));
+        let rr = rr.observer(Recorder(events.clone()));
+
+        // Attempts at 0s, 1s and 2s, and the deadline hits while sleeping
+        let start = Instant::now();
+        let opts = RunOptions::new().timeout(Duration::from_millis(2500));
+        let res = rr.run_with(opts, |_| async { Ok(()) }).await;
+
+        assert_eq!(res, Err(Error::RoundRobin(crate::Error::DeadlineExceeded)));
+        assert_eq!(count_conn.load(Ordering::Relaxed), 3);
+        assert_eq!(start.elapsed(), Duration::from_millis(2500));
+        assert_eq!(exhausted(), 1);
+
+        // The in-flight attempt is cancelled
+        let (rr, _) = build_rr(vec![0, 1], 0);
+        let rr = rr.observer(Recorder(events.clone()));
+        let count = AtomicUsize::new(0);
+
+        let start = Instant::now();
+        let opts = RunOptions::new().deadline(start + Duration::from_secs(1));
+        let res = rr
+            .run_with(opts, |_| {
+                count.fetch_add(1, Ordering::Relaxed);
+                std::future::pending::<Result<(), _>>()
+            })
+            .await;
+
+        assert_eq!(res, Err(Error::RoundRobin(crate::Error::DeadlineExceeded)));
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+        assert_eq!(exhausted(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_attempt_budget() {
+        let (rr, _) = build_rr(vec![0, 1], 0);
+        let budgets = std::sync::Mutex::new(Vec::new());
+
+        let opts = RunOptions::new().timeout(Duration::from_secs(10));
+        let res = rr
+            .run_with_attempt(opts, |n, attempt| {
+                budgets.lock().unwrap().push((
+                    attempt.number(),
+                    attempt.index(),
+                    attempt.remaining(),
+                ));
+                async move {
+                    if *n == 0 {
+                        tokio::time::sleep(Duration::from_secs(4)).await;
+                        return Err(Error::Timeout);
+                    }
+                    Ok(*n)
+                }
+            })
+            .await;
+
+        assert_eq!(res, Ok(1));
+        assert_eq!(
+            *budgets.lock().unwrap(),
+            [(1, 0, Some(Duration::from_secs(10))), (2, 1, Some(Duration::from_secs(6)))],
+        );
+
+        // The run timeout applies if shorter than the remaining budget
+        let rr = rr.run_timeout(Duration::from_secs(3));
+        let opts = RunOptions::new().timeout(Duration::from_secs(10));
+        let remaining =
+            rr.run_with_attempt(opts, |_, attempt| async move { Ok(attempt.remaining()) });
+
+        assert_eq!(remaining.await, Ok(Some(Duration::from_secs(3))));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_circuit_breaker() {
+        let (rr, count_conn) = build_rr(vec![0, 1], 1);
+        let rr = rr.circuit_breaker(CircuitBreaker::new(1, Duration::from_secs(10)));
+
+        // Service 0 fails and its circuit opens
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, Ok(1));
+        assert_eq!(count_conn.load(Ordering::Relaxed), 2);
+        assert_eq!(rr.circuit_state(0), Some(CircuitState::Open));
+        assert_eq!(rr.circuit_state(1), Some(CircuitState::Closed));
+        assert_eq!(rr.circuit_state(2), None);
+
+        // Service 1 fails too, and service 0 is not retried
+        let res = rr.run(|_| async { Err::<(), _>(Error::Timeout) }).await;
+        assert_eq!(res, Err(Error::Timeout));
+        assert_eq!(count_conn.load(Ordering::Relaxed), 2);
+        assert_eq!(rr.circuit_state(1), Some(CircuitState::Open));
+
+        // All circuits are open: fail fast
+        let res = rr.run(|n| async move { Ok(*n) }).await;
+        assert_eq!(res, Err(Error::RoundRobin(crate::Error::CircuitOpen)));
+        assert_eq!(count_conn.load(Ordering::Relaxed), 2);
+
+        // After the cooldown, services are probed again: 0 is still down, but 1 is back up
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, Ok(1));
+        assert_eq!(count_conn.load(Ordering::Relaxed), 4);
+        assert_eq!(rr.circuit_state(1), Some(CircuitState::Closed));
+        assert_eq!(rr.circuit_state(0), Some(CircuitState::Open));
+    }
+
+    /// Health check reporting as unhealthy the sources set in `down`
+    struct Check {
+        down: Arc<std::sync::Mutex<Vec<i32>>>,
+        count: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl HealthCheck<i32> for Check {
+        async fn check(&self, src: &i32) -> bool {
+            self.count.fetch_add(1, Ordering::Relaxed);
+            !self.down.lock().unwrap().contains(src)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_health_check() {
+        let down = Arc::new(std::sync::Mutex::new(vec![0]));
+        let count_check = Arc::new(AtomicUsize::new(0));
+        let check = Check { down: down.clone(), count: count_check.clone() };
+        let (rr, count_conn) = build_rr(vec![0, 1], 0);
+        let rr = rr.health_check(check, Duration::from_secs(1));
+
+        // Let the first check happen
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert_eq!(count_check.load(Ordering::Relaxed), 2);
+        assert_eq!(rr.is_healthy(0), Some(false));
+
+        // The unhealthy service is never connected to
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, Ok(1));
+        assert_eq!(count_conn.load(Ordering::Relaxed), 1);
+
+        // The connected service becomes unhealthy: move on to the next one
+        *down.lock().unwrap() = vec![1];
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, Ok(0));
+        assert_eq!(count_conn.load(Ordering::Relaxed), 2);
+
+        *down.lock().unwrap() = vec![0, 1];
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let res = rr.run(|n| async move { Ok(*n) }).await;
+        assert_eq!(res, Err(Error::RoundRobin(crate::Error::Unhealthy)));
+
+        // Dropping the round-robin stops the checks
+        drop(rr);
+        let checks = count_check.load(Ordering::Relaxed);
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert_eq!(count_check.load(Ordering::Relaxed), checks);
+    }
+
+    /// Health check never answering for the source 0, and reporting the other ones as unhealthy
+    struct Hanging;
+
+    #[async_trait]
+    impl HealthCheck<i32> for Hanging {
+        async fn check(&self, src: &i32) -> bool {
+            if *src == 0 {
+                std::future::pending::<()>().await;
+            }
+            false
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_health_check_timeout() {
+        let (rr, _) = build_rr(vec![0, 1], 0);
+        let rr = rr.health_check(Hanging, Duration::from_secs(1));
+
+        // The hanging probe does not hold back the other ones, and fails after a period
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert_eq!((rr.is_healthy(0), rr.is_healthy(1)), (Some(true), Some(false)));
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(rr.is_healthy(0), Some(false));
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "must not be zero")]
+    async fn test_health_check_zero_period() {
+        let (rr, _) = build_rr(vec![0, 1], 0);
+        let _rr = rr.health_check(Hanging, Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_update_sources() {
+        let (rr, count_conn) = build_rr(vec![0, 1, 2], 0);
+
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, Ok(0));
+        assert!(!rr.remove_source(&42));
+
+        // Removing the connected source reconnects to the next one
+        assert!(rr.remove_source(&0));
+        assert_eq!(rr.sources(), [1, 2]);
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, Ok(1));
+        assert_eq!(count_conn.load(Ordering::Relaxed), 2);
+
+        // The connection is kept if its source is kept
+        rr.add_source(3);
+        rr.replace_sources(vec![3, 1]);
+        assert_eq!(rr.sources(), [3, 1]);
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, Ok(1));
+        assert_eq!(count_conn.load(Ordering::Relaxed), 2);
+
+        // Calls in flight complete against the removed source
+        let removed = tokio::sync::Notify::new();
+        let (res, _) = tokio::join!(
+            rr.run(|n| {
+                let removed = &removed;
+                async move {
+                    removed.notified().await;
+                    Ok(*n)
+                }
+            }),
+            async {
+                tokio::task::yield_now().await;
+                assert!(rr.remove_source(&1));
+                removed.notify_one();
+            },
+        );
+        assert_eq!(res, Ok(1));
 
-        assert_eq!(count_conn.load(Ordering::Relaxed), 1);
-        match res {
-            Ok(_) => panic!("Run did not error"),
-            Err(Error::Timeout) => (),
-            Err(_) => panic!("Wrong error"),
+        // Then the next service is used, wrapping around
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, Ok(3));
+        assert_eq!(count_conn.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_discovery() {
+        use discovery::{DnsDiscovery, SrvRecord, StaticResolver};
+
+        let srv = |priority, port| SrvRecord {
+            priority,
+            weight: 0,
+            port,
+            target: "svc.example.".into(),
+            ttl: Duration::from_secs(10),
+        };
+        let resolver = Arc::new(StaticResolver::new());
+        resolver.set_srv("_svc._tcp.example", vec![srv(20, 3), srv(10, 2)]);
+
+        let discovery = DnsDiscovery::srv(resolver.clone(), "_svc._tcp.example");
+        let (rr, _) = build_rr(vec![0, 1], 2);
+        let rr = rr.discovery(discovery.map(|ep| i32::from(ep.port)));
+
+        // Let the first discovery happen, ordering targets by priority
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert_eq!(rr.sources(), [2, 3]);
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, Ok(2));
+
+        // Records expire
+        resolver.set_srv("_svc._tcp.example", vec![srv(10, 4)]);
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert_eq!(rr.sources(), [4]);
+
+        // An empty answer keeps the current sources
+        resolver.set_srv("_svc._tcp.example", vec![]);
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert_eq!(rr.sources(), [4]);
+
+        // Exhaustion triggers a refresh, without waiting for expiry
+        resolver.set_srv("_svc._tcp.example", vec![srv(10, 5)]);
+        let res = rr.run(|_| async { Err::<(), _>(Error::Timeout) }).await;
+        assert_eq!(res, Err(Error::Timeout));
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert_eq!(rr.sources(), [5]);
+    }
+
+    /// Connector failing for the sources set in `down`
+    struct Down(Arc<std::sync::Mutex<Vec<i32>>>);
+
+    #[async_trait]
+    impl Connector<i32, i32, Error> for Down {
+        async fn connect(&self, src: &i32) -> Result<i32, Error> {
+            if self.0.lock().unwrap().contains(src) {
+                Err(Error::Timeout)
+            } else {
+                Ok(*src)
+            }
         }
-        assert_eq!(count.load(Ordering::Relaxed), 1);
     }
 
-    #[tokio::test]
-    async fn test_try_next_run() {
-        let (rr, count_conn) = build_rr(vec![0, 1], 0);
+    #[tokio::test(start_paused = true)]
+    async fn test_tiers() {
+        let down = Arc::new(std::sync::Mutex::new(vec![0, 1]));
+        let rr = RoundRobin::tiered(vec![vec![0, 1], vec![2, 3]], Down(down.clone()))
+            .failback(Duration::from_secs(10));
 
-        let count = AtomicUsize::new(0);
+        // The fallback tier is only used once the preferred one is down
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, Ok(2));
 
-        rr.run(|n| {
-            count.fetch_add(1, Ordering::Relaxed);
+        // The preferred tier is probed, but still down
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, Ok(2));
+
+        // It recovered, but the next probe is not due yet
+        down.lock().unwrap().clear();
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, Ok(2));
+
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, Ok(0));
+
+        rr.add_source_in_tier(4, 1);
+        rr.add_source(5);
+        assert_eq!(rr.sources(), [0, 1, 5, 2, 3, 4]);
+    }
+
+    /// Error with a rich classification
+    #[derive(Debug, PartialEq)]
+    enum Rich {
+        Busy(Duration),
+        Stale,
+        Down,
+        Fatal,
+        RoundRobin(crate::Error),
+    }
+
+    impl From<crate::Error> for Rich {
+        fn from(e: crate::Error) -> Self {
+            Self::RoundRobin(e)
+        }
+    }
+
+    impl std::fmt::Display for Rich {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{:?}", self)
+        }
+    }
+
+    impl Classify for Rich {
+        fn classify(&self) -> Classification {
+            match self {
+                Self::Busy(after) => Classification::Retry(Some(*after)),
+                Self::Stale => Classification::Reconnect(None),
+                Self::Down => Classification::Next(None),
+                Self::Fatal => Classification::Fatal,
+                Self::RoundRobin(e) => e.classify(),
+            }
+        }
+    }
+
+    /// Connector counting connections
+    struct Counting(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl Connector<i32, i32, Rich> for Counting {
+        async fn connect(&self, src: &i32) -> Result<i32, Rich> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(*src)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_classify() {
+        let count_conn = Arc::new(AtomicUsize::new(0));
+        let rr = RoundRobin::new(vec![0, 1], Counting(count_conn.clone()));
+
+        // Fail once with the given error, then succeed
+        let run_once = |error: fn() -> Rich| {
+            let count = AtomicUsize::new(0);
+            let rr = &rr;
             async move {
-                match *n {
-                    0 => Err(Error::Timeout), // Next error
-                    _ => Ok(n),
-                }
+                let res = rr
+                    .run(|n| {
+                        let first = count.fetch_add(1, Ordering::Relaxed) == 0;
+                        async move {
+                            if first {
+                                Err(error())
+                            } else {
+                                Ok(*n)
+                            }
+                        }
+                    })
+                    .await;
+                (res, count.load(Ordering::Relaxed))
             }
-        })
-        .await
-        .unwrap();
+        };
 
+        // Retry on the same connection, waiting the suggested delay
+        let start = Instant::now();
+        assert_eq!(run_once(|| Rich::Busy(Duration::from_secs(1))).await, (Ok(0), 2));
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+        assert_eq!(count_conn.load(Ordering::Relaxed), 1);
+
+        // Reconnect to the same service
+        assert_eq!(run_once(|| Rich::Stale).await, (Ok(0), 2));
         assert_eq!(count_conn.load(Ordering::Relaxed), 2);
-        assert_eq!(count.load(Ordering::Relaxed), 2);
+
+        // Move on to the next service
+        assert_eq!(run_once(|| Rich::Down).await, (Ok(1), 2));
+        assert_eq!(count_conn.load(Ordering::Relaxed), 3);
+
+        assert_eq!(run_once(|| Rich::Fatal).await, (Err(Rich::Fatal), 1));
+        assert_eq!(count_conn.load(Ordering::Relaxed), 3);
     }
 
-    #[tokio::test]
-    async fn test_exhausted_connector() {
-        let (mut rr, count_conn) = build_rr(vec![0, 1], 1);
+    /// Connector failing fatally after a while, counting connections
+    struct Fatal(Arc<AtomicUsize>);
 
-        let count = AtomicUsize::new(0);
+    #[async_trait]
+    impl Connector<i32, i32, Rich> for Fatal {
+        async fn connect(&self, _: &i32) -> Result<i32, Rich> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Err(Rich::Fatal)
+        }
+    }
 
-        // With a single attemt, options will be exhausted
-        rr.set_max_attempts(1);
+    #[tokio::test(start_paused = true)]
+    async fn test_single_flight_fatal() {
+        let count_conn = Arc::new(AtomicUsize::new(0));
+        let rr = RoundRobin::new(vec![0, 1], Fatal(count_conn.clone()));
 
-        let res = rr.run(|_| async { Ok(count.fetch_add(1, Ordering::Relaxed)) }).await;
+        // Callers waiting for a connection failing fatally share its error
+        let run = || rr.run_with_attempt(RunOptions::new(), |n, _| async move { Ok(*n) });
+        let shared = |res: Result<i32, _>| match res {
+            Err(RunError::Connect(e)) => e,
+            res => panic!("not a shared connection error: {:?}", res),
+        };
+        let (a, b, c) = tokio::join!(run(), run(), run());
+        let (a, b, c) = (shared(a), shared(b), shared(c));
+        assert_eq!(*a, Rich::Fatal);
+        assert!(Arc::ptr_eq(&a, &b) && Arc::ptr_eq(&a, &c));
+        assert_eq!(count_conn.load(Ordering::Relaxed), 1);
+    }
+
+    /// Connector denying access after a while, counting connections
+    struct Denied(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl Connector<i32, i32, IoError> for Denied {
+        async fn connect(&self, _: &i32) -> Result<i32, IoError> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Err(ErrorKind::PermissionDenied.into())
+        }
+    }
 
+    #[tokio::test(start_paused = true)]
+    async fn test_single_flight_denied() {
+        let count_conn = Arc::new(AtomicUsize::new(0));
+        let rr = RoundRobin::new(vec![0, 1], Denied(count_conn.clone()));
+
+        // The error is shared by all callers, converted from the same `ConnectFailed`
+        let run = || async { rr.run(|n| async move { Ok(*n) }).await.unwrap_err() };
+        let (a, b, c, d, e) = tokio::join!(run(), run(), run(), run(), run());
+        let errors = [a, b, c, d, e];
         assert_eq!(count_conn.load(Ordering::Relaxed), 1);
-        match res {
-            Ok(_) => panic!("Connect did not error"),
-            Err(Error::Timeout) => (),
-            Err(_) => panic!("Wrong error"),
+
+        let inner = |e: &IoError| e.get_ref().unwrap().downcast_ref::<crate::Error>().cloned();
+        for e in &errors {
+            assert_eq!(e.kind(), ErrorKind::PermissionDenied);
+            assert_eq!(inner(e), inner(&errors[0]));
         }
-        assert_eq!(count.load(Ordering::Relaxed), 0);
+        match inner(&errors[0]) {
+            Some(crate::Error::ConnectFailed(e)) => {
+                assert_eq!(e.downcast_ref::<IoError>().unwrap().kind(), ErrorKind::PermissionDenied)
+            }
+            e => panic!("not a shared connection error: {:?}", e),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_run_with_errors() {
+        let (rr, _) = build_rr(vec![0, 1, 2], 3);
+        let rr = rr.backoff(Constant(Duration::from_secs(1)));
+
+        let errors = rr.run_with_errors(RunOptions::new(), |n, _| async move { Ok(*n) }).await;
+        let errors = errors.unwrap_err();
+
+        let failures = errors.failures();
+        assert_eq!(failures.iter().map(|f| f.index).collect::<Vec<_>>(), [0, 1, 2, 0]);
+        assert_eq!(failures[1].source, "1");
+        assert_eq!(failures[3].elapsed, Duration::from_secs(3));
+        assert!(failures.iter().all(|f| f.error == RunError::Service(Error::Timeout)));
+        assert!(errors.to_string().starts_with("4 attempts failed: service 0 (0) after 0ns"));
+
+        // Fatal errors stop right away
+        let (rr, _) = build_rr(vec![0, 1, 2], 0);
+        let run = |n: Arc<i32>, _| async move {
+            match *n {
+                0 => Err::<(), _>(Error::Timeout),
+                _ => Err(Error::NotFound),
+            }
+        };
+        let errors = rr.run_with_errors(RunOptions::new(), run).await.unwrap_err();
+        assert_eq!(errors.failures().len(), 2);
+        assert_eq!(errors.into_last(), RunError::Service(Error::NotFound));
+
+        // Sources are represented with the redaction function
+        let (rr, _) = build_rr(vec![0, 1], 2);
+        let rr = rr.redact(|n, f| write!(f, "service-{}", n));
+        let errors = rr.run_with_errors(RunOptions::new(), |n, _| async move { Ok(*n) }).await;
+        assert_eq!(errors.unwrap_err().failures()[1].source, "service-1");
+        assert_eq!(rr.stats()[0].source, "service-0");
+    }
+
+    /// Connector slowly refusing every connection, naming the source in the error
+    struct Refusing;
+
+    #[async_trait]
+    impl Connector<i32, i32, std::io::Error> for Refusing {
+        async fn connect(&self, src: &i32) -> Result<i32, std::io::Error> {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let msg = format!("refused by {}", src);
+            Err(std::io::Error::new(std::io::ErrorKind::ConnectionRefused, msg))
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_run_with_errors_concurrent() {
+        let rr = RoundRobin::new(vec![0, 1, 2], Refusing);
+
+        // Callers waiting for a connection in progress blame the service it was made against
+        let run = || rr.run_with_errors(RunOptions::new(), |n, _| async move { Ok(*n) });
+        let (a, b) = tokio::join!(run(), run());
+
+        for errors in [a.unwrap_err(), b.unwrap_err()] {
+            for failure in errors.failures() {
+                assert_eq!(failure.error.to_string(), format!("refused by {}", failure.index));
+                assert_eq!(failure.source, failure.index.to_string());
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_run_with_outcome() {
+        let (rr, _) = build_rr(vec![0, 1, 2], 2);
+        let rr = rr.backoff(Constant(Duration::from_secs(1)));
+
+        let outcome = rr.run_with_outcome(RunOptions::new(), |n, _| async move { Ok(*n * 10) });
+        let outcome = outcome.await.unwrap();
+
+        assert_eq!((outcome.value, outcome.index, outcome.source), (20, 2, 2));
+        assert_eq!(outcome.attempts, 3);
+        assert_eq!(outcome.latency, Duration::from_secs(2));
+
+        // The connection is reused
+        let outcome = rr.run_with_outcome(RunOptions::new(), |n, _| async move { Ok(*n) });
+        let outcome = outcome.await.unwrap();
+        assert_eq!((outcome.index, outcome.attempts, outcome.latency), (2, 1, Duration::ZERO));
     }
 
     #[tokio::test]
-    async fn test_try_next_connector() {
-        let (rr, count_conn) = build_rr(vec![0, 1], 1);
+    async fn test_run_with_source() {
+        let (rr, _) = build_rr(vec![3, 4, 5], 4);
 
-        let count = AtomicUsize::new(0);
+        let run = |n: Arc<i32>, src: &i32, index| {
+            let src = *src;
+            async move { Ok((*n, src, index)) }
+        };
+        assert_eq!(rr.run_with_source(run).await, Ok((4, 4, 1)));
+    }
 
-        rr.run(|_| async { Ok(count.fetch_add(1, Ordering::Relaxed)) }).await.unwrap();
+    /// Connector taking some time to connect, failing for sources below `ok_from`
+    struct Slow {
+        count: Arc<AtomicUsize>,
+        ok_from: i32,
+    }
+
+    #[async_trait]
+    impl Connector<i32, i32, Error> for Slow {
+        async fn connect(&self, src: &i32) -> Result<i32, Error> {
+            self.count.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            if *src < self.ok_from {
+                Err(Error::Timeout)
+            } else {
+                Ok(*src)
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_single_flight_connect() {
+        let count_conn = Arc::new(AtomicUsize::new(0));
+        let slow = Slow { count: count_conn.clone(), ok_from: 0 };
+        let rr = Arc::new(RoundRobin::new(vec![0, 1], slow));
+
+        let mut tasks = tokio::task::JoinSet::new();
+        for _ in 0..100 {
+            let rr = rr.clone();
+            tasks.spawn(async move { rr.run(|n| async move { Ok(*n) }).await });
+        }
 
+        while let Some(res) = tasks.join_next().await {
+            assert_eq!(res.unwrap(), Ok(0));
+        }
+        assert_eq!(count_conn.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_single_flight_failback() {
+        let count_conn = Arc::new(AtomicUsize::new(0));
+        let rr = RoundRobin::builder()
+            .tiers(vec![vec![0], vec![1]])
+            .connector(Slow { count: count_conn.clone(), ok_from: 0 })
+            .start(Start::At(1))
+            .failback(Duration::from_secs(10))
+            .build()
+            .unwrap();
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, Ok(1));
+
+        // A call failing over during the failback waits for it, rather than connecting on its own
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        let failing = |n: Arc<i32>| async move {
+            match *n {
+                1 => Err(Error::Timeout),
+                n => Ok(n),
+            }
+        };
+        let (a, b) = tokio::join!(rr.run(|n| async move { Ok(*n) }), rr.run(failing));
+        assert_eq!((a, b), (Ok(0), Ok(0)));
         assert_eq!(count_conn.load(Ordering::Relaxed), 2);
-        assert_eq!(count.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_single_flight_failure() {
+        let count_conn = Arc::new(AtomicUsize::new(0));
+        let rr = RoundRobin::new(vec![0, 1, 2], Slow { count: count_conn.clone(), ok_from: 1 });
+
+        // Callers waiting for a failed connection move on along with it
+        let run = || rr.run(|n| async move { Ok(*n) });
+        let res = tokio::join!(run(), run(), run(), run());
+        assert_eq!(res, (Ok(1), Ok(1), Ok(1), Ok(1)));
+        assert_eq!(count_conn.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_coordinated_failover() {
+        let (rr, count_conn) = build_rr(vec![0, 1, 2, 3], 0);
+        let rr = Arc::new(rr);
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, Ok(0));
+
+        // All calls in flight fail at once on the first service, which moves on only once
+        let barrier = Arc::new(tokio::sync::Barrier::new(50));
+        let mut tasks = tokio::task::JoinSet::new();
+        for _ in 0..50 {
+            let (rr, barrier) = (rr.clone(), barrier.clone());
+            tasks.spawn(async move {
+                let run = |n: Arc<i32>| {
+                    let barrier = barrier.clone();
+                    async move {
+                        if *n == 0 {
+                            barrier.wait().await;
+                            return Err(Error::Timeout);
+                        }
+                        Ok(*n)
+                    }
+                };
+                rr.run(run).await
+            });
+        }
+
+        while let Some(res) = tasks.join_next().await {
+            assert_eq!(res.unwrap(), Ok(1));
+        }
+        assert_eq!(rr.sources.current.load(Ordering::Relaxed), 1);
+        assert_eq!(count_conn.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stale_failure() {
+        let (rr, count_conn) = build_rr(vec![0, 1, 2], 0);
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, Ok(0));
+        let release = &tokio::sync::Notify::new();
+
+        // A failure on a connection that was already replaced does not move on again
+        let slow = rr.run(|n| async move {
+            if *n == 0 {
+                release.notified().await;
+                return Err(Error::Timeout);
+            }
+            Ok(*n)
+        });
+        let fast = async {
+            tokio::task::yield_now().await;
+            let fast = rr.run(|n| async move {
+                if *n == 0 {
+                    Err(Error::Timeout)
+                } else {
+                    Ok(*n)
+                }
+            });
+            let res = fast.await;
+            release.notify_waiters();
+            res
+        };
+
+        assert_eq!(tokio::join!(slow, fast), (Ok(1), Ok(1)));
+        assert_eq!(rr.sources.current.load(Ordering::Relaxed), 1);
+        assert_eq!(count_conn.load(Ordering::Relaxed), 2);
+    }
+
+    /// Observer recording events as strings
+    struct Recorder(Arc<std::sync::Mutex<Vec<String>>>);
+
+    impl Observer<i32, Error> for Recorder {
+        fn on_connect(&self, index: usize, src: &i32) {
+            self.0.lock().unwrap().push(format!("connect {} {}", index, src));
+        }
+
+        fn on_connected(&self, index: usize, src: &i32) {
+            self.0.lock().unwrap().push(format!("connected {} {}", index, src));
+        }
+
+        fn on_connect_error(&self, index: usize, src: &i32, error: &RunError<Error>) {
+            self.0.lock().unwrap().push(format!("connect error {} {} {:?}", index, src, error));
+        }
+
+        fn on_next_error(&self, index: usize, src: &i32, error: &RunError<Error>) {
+            self.0.lock().unwrap().push(format!("next error {} {} {:?}", index, src, error));
+        }
+
+        fn on_failover(&self, from: usize, from_src: &i32, to: usize, to_src: &i32) {
+            let event = format!("failover {} {} -> {} {}", from, from_src, to, to_src);
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn on_exhausted(&self, errors: &Exhausted<Error>) {
+            self.0.lock().unwrap().push(format!("exhausted {}", errors.failures().len()));
+        }
     }
 
     #[tokio::test]
-    async fn test_abort() {
-        let (rr, _) = build_rr(vec![0, 1], 1);
+    async fn test_observer() {
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let (rr, _) = build_rr(vec![10, 11, 12], 11);
+        let mut rr = rr.observer(Recorder(events.clone()));
+
+        let run = |n: Arc<i32>| async move {
+            match *n {
+                11 => Err(Error::Timeout),
+                n => Ok(n),
+            }
+        };
+        assert_eq!(rr.run(run).await, Ok(12));
+        assert_eq!(
+            events.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            [
+                "connect 0 10",
+                "connect error 0 10 Service(Timeout)",
+                "failover 0 10 -> 1 11",
+                "connect 1 11",
+                "connected 1 11",
+                "next error 1 11 Service(Timeout)",
+                "failover 1 11 -> 2 12",
+                "connect 2 12",
+                "connected 2 12",
+            ]
+        );
+
+        rr.set_max_attempts(2);
+        assert_eq!(rr.run(|_| async { Err::<(), _>(Error::Timeout) }).await, Err(Error::Timeout));
+        assert_eq!(
+            events.lock().unwrap().drain(..).collect::<Vec<_>>(),
+            [
+                "next error 2 12 Service(Timeout)",
+                "failover 2 12 -> 0 10",
+                "connect 0 10",
+                "connect error 0 10 Service(Timeout)",
+                "failover 0 10 -> 1 11",
+                "exhausted 2",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let (rr, _) = build_rr(vec![0, 1, 2], 1);
+        let rr = rr.max_attempts(1);
+        let mut status = rr.subscribe();
+        assert_eq!(*status.borrow_and_update(), Status::default());
+
+        // Failing over to the next service on connection
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, Err(Error::Timeout));
+        assert!(status.has_changed().unwrap());
+        let expected = Status { index: 1, last_error: Some("Timeout".into()), ..Status::default() };
+        assert_eq!(*status.borrow_and_update(), expected);
+
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, Ok(1));
+        let expected = Status { connected: true, generation: 1, ..expected };
+        assert_eq!(*status.borrow_and_update(), expected);
+
+        // Failing over to the next service on next errors
+        let res = rr.run(|_| async { Err::<(), _>(Error::Timeout) }).await;
+        assert_eq!(res, Err(Error::Timeout));
+        assert_eq!(
+            *status.borrow_and_update(),
+            Status { index: 2, connected: false, ..expected.clone() }
+        );
 
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, Ok(2));
+        assert_eq!(rr.status(), Status { index: 2, connected: true, generation: 2, ..expected });
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stats() {
+        let (rr, _) = build_rr(vec![0, 1], 1);
+        let run = |n: Arc<i32>| async move {
+            tokio::time::sleep(Duration::from_millis(10 * *n as u64)).await;
+            Ok(*n)
+        };
+        assert_eq!(rr.run(run).await, Ok(1));
+        assert_eq!(rr.run(run).await, Ok(1));
         let res = rr.run(|_| async { Err::<(), _>(Error::NotFound) }).await;
+        assert_eq!(res, Err(Error::NotFound));
 
-        match res {
-            Ok(_) => panic!("Run did not error"),
-            Err(Error::NotFound) => (),
-            Err(Error::Timeout) => panic!("Connector error aborted"),
+        let stats = rr.stats();
+        assert_eq!((stats[0].index, stats[0].source.as_str()), (0, "0"));
+        assert_eq!((stats[0].connects, stats[0].connect_failures), (1, 1));
+        assert_eq!((stats[0].failovers, stats[0].consecutive_failures), (1, 1));
+        assert!(stats[0].last_success.is_none() && stats[0].last_failure.is_some());
+
+        assert_eq!((stats[1].connects, stats[1].connect_failures), (1, 0));
+        assert_eq!((stats[1].successes, stats[1].failures), (2, 1));
+        assert_eq!((stats[1].failovers, stats[1].consecutive_failures), (0, 1));
+        assert!(stats[1].last_success.is_some() && stats[1].last_failure.is_some());
+
+        let latency = stats[1].run_latency;
+        assert_eq!(
+            (latency.count, latency.min, latency.max),
+            (3, Duration::ZERO, Duration::from_millis(10))
+        );
+        assert_eq!(latency.mean(), latency.total / 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_no_sources() {
+        let no_sources = || Err(Error::RoundRobin(crate::Error::NoSources));
+        let (rr, count_conn) = build_rr(vec![], 0);
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, no_sources());
+        assert!(rr.stats().is_empty());
+
+        // Calls fail as well once all sources were removed
+        rr.add_source(1);
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, Ok(1));
+        assert!(rr.remove_source(&1));
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, no_sources());
+        rr.add_source(1);
+        rr.replace_sources(vec![]);
+        assert_eq!(rr.run(|n| async move { Ok(*n) }).await, no_sources());
+        assert_eq!(count_conn.load(Ordering::Relaxed), 1);
+
+        // Calls wait for sources to be added, for at most the configured time
+        let rr = rr.wait_for_sources(Duration::from_secs(1));
+        let start = Instant::now();
+        let add = async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            rr.add_source(2);
+        };
+        let (res, _) = tokio::join!(rr.run(|n| async move { Ok(*n) }), add);
+        assert_eq!((res, start.elapsed()), (Ok(2), Duration::from_millis(100)));
+
+        rr.replace_sources(vec![]);
+        let start = Instant::now();
+        let res = rr.run(|n| async move { Ok(*n) }).await;
+        assert_eq!((res, start.elapsed()), (no_sources(), Duration::from_secs(1)));
+
+        // The wait is bounded by the deadline of the call
+        let start = Instant::now();
+        let opts = RunOptions::new().timeout(Duration::from_millis(200));
+        let res = rr.run_with(opts, |n| async move { Ok(*n) }).await;
+        assert_eq!((res, start.elapsed()), (no_sources(), Duration::from_millis(200)));
+    }
+
+    /// Error type that is not convertible from the round-robin's errors
+    #[derive(Debug, PartialEq)]
+    struct Refused(i32);
+
+    impl std::fmt::Display for Refused {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "refused by {}", self.0)
+        }
+    }
+
+    impl Next for Refused {
+        fn is_next(&self) -> bool {
+            true
+        }
+    }
+
+    /// Connector refusing sources below 1
+    struct Picky;
+
+    #[async_trait]
+    impl Connector<i32, i32, Refused> for Picky {
+        async fn connect(&self, src: &i32) -> Result<i32, Refused> {
+            if *src < 1 {
+                Err(Refused(*src))
+            } else {
+                Ok(*src)
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unconvertible_error() {
+        let rr = RoundRobin::new(vec![0, 1], Picky);
+        let res = rr.run_with_errors(RunOptions::new(), |n, _| async move { Ok(*n) }).await;
+        assert_eq!(res.unwrap(), 1);
+
+        let run = |n: Arc<i32>, _| async move { Err::<(), _>(Refused(*n)) };
+        let errors = rr.run_with_errors(RunOptions::new(), run).await.unwrap_err();
+        let errors: Vec<_> = errors.into_failures().into_iter().map(|f| f.error).collect();
+        let refused = |n| RunError::Service(Refused(n));
+        assert_eq!(errors, [refused(1), refused(0), refused(1)]);
+
+        // Tourniquet's own errors are reported as such
+        let opts = RunOptions::new().timeout(Duration::from_secs(1));
+        let run = |_, _| std::future::pending::<Result<(), _>>();
+        let errors = rr.run_with_errors(opts, run).await.unwrap_err();
+        assert_eq!(errors.last(), &RunError::Tourniquet(crate::Error::DeadlineExceeded));
+
+        rr.replace_sources(vec![]);
+        let errors = rr.run_with_errors(RunOptions::new(), |n, _| async move { Ok(*n) });
+        let errors = errors.await.unwrap_err();
+        assert_eq!(errors.into_last(), RunError::Tourniquet(crate::Error::NoSources));
+
+        // As do the other functions not returning the error type itself
+        let res = rr.run_with_attempt(RunOptions::new(), |n, _| async move { Ok(*n) }).await;
+        assert_eq!(res, Err(RunError::Tourniquet(crate::Error::NoSources)));
+        rr.add_source(1);
+        let outcome = rr.run_with_outcome(RunOptions::new(), |n, _| async move { Ok(*n) }).await;
+        assert_eq!(outcome.map(|o| (o.value, o.index)), Ok((1, 0)));
+    }
+
+    /// Connector that never answers for the given source
+    struct Blackhole(i32);
+
+    #[async_trait]
+    impl Connector<i32, i32, Error> for Blackhole {
+        async fn connect(&self, src: &i32) -> Result<i32, Error> {
+            if *src == self.0 {
+                std::future::pending::<()>().await;
+            }
+            Ok(*src)
         }
     }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_connect_timeout() {
+        let timeout = Duration::from_secs(1);
+        let rr = RoundRobin::new(vec![0, 1], Blackhole(0)).connect_timeout(timeout);
+
+        let start = Instant::now();
+        let n = rr.run(|n| async move { Ok(*n) }).await.unwrap();
+
+        assert_eq!(n, 1);
+        assert_eq!(start.elapsed(), timeout);
+
+        let rr = RoundRobin::new(vec![0], Blackhole(0)).connect_timeout(timeout);
+        let res = rr.run(|n| async move { Ok(*n) }).await;
+
+        assert_eq!(res, Err(Error::RoundRobin(crate::Error::ConnectTimeout(timeout))));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_run_timeout() {
+        let timeout = Duration::from_secs(1);
+        let (rr, count_conn) = build_rr(vec![0, 1], 0);
+        let rr = rr.run_timeout(timeout);
+
+        let run = |n: Arc<i32>| async move {
+            if *n == 0 {
+                std::future::pending::<()>().await;
+            }
+            Ok(*n)
+        };
+
+        let start = Instant::now();
+        assert_eq!(rr.run(run).await, Ok(1));
+        assert_eq!(start.elapsed(), timeout);
+        // The hung service was dropped, and we reconnected to the next one
+        assert_eq!(count_conn.load(Ordering::Relaxed), 2);
+
+        let (rr, _) = build_rr(vec![0], 0);
+        let rr = rr.run_timeout(timeout).max_attempts(2);
+
+        assert_eq!(rr.run(run).await, Err(Error::RoundRobin(crate::Error::RunTimeout(timeout))));
+    }
 }
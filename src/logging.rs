@@ -0,0 +1,408 @@
+//! Logging of failures.
+//!
+//! By default, every failed attempt is logged as an error, which floods the logs during an outage
+//! as every call fails the same way. [`Logging`] sets the level of each kind of event, and can
+//! rate limit them per service, reporting how many were suppressed.
+//!
+//! Events are logged with the `tourniquet` target, and their details as structured key-values
+//! rather than in the message: `index` and `service` (see [`Redact`]) of the service, `error`,
+//! and `suppressed`, the number of similar events suppressed since the last logged one. This
+//! includes the events of the background tasks: health changes, failbacks and discovery failures.
+
+use std::{fmt::Display, sync::Mutex, time::Duration};
+
+use log::{Level, LevelFilter};
+use tokio::time::Instant;
+
+use crate::{
+    redact::{Redact, Redacted},
+    sources::Source,
+};
+
+const TARGET: &str = "tourniquet";
+
+/// Logging configuration.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// use log::LevelFilter;
+/// use tourniquet::logging::Logging;
+///
+/// // Log failed attempts as warnings along with failovers, at most every 10 seconds per service
+/// let logging = Logging::default()
+///     .failure(LevelFilter::Warn)
+///     .failover(LevelFilter::Info)
+///     .rate_limit(Duration::from_secs(10));
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Logging {
+    failure: LevelFilter,
+    failover: LevelFilter,
+    exhausted: LevelFilter,
+    health: LevelFilter,
+    failback: LevelFilter,
+    discovery: LevelFilter,
+    rate_limit: Option<Duration>,
+}
+
+impl Default for Logging {
+    fn default() -> Self {
+        Self {
+            failure: LevelFilter::Error,
+            failover: LevelFilter::Off,
+            exhausted: LevelFilter::Off,
+            health: LevelFilter::Warn,
+            failback: LevelFilter::Info,
+            discovery: LevelFilter::Warn,
+            rate_limit: None,
+        }
+    }
+}
+
+impl Logging {
+    /// Level of failed attempts, `Error` by default.
+    pub fn failure(self, level: LevelFilter) -> Self {
+        Self { failure: level, ..self }
+    }
+
+    /// Level of moves to the next service, `Off` by default.
+    pub fn failover(self, level: LevelFilter) -> Self {
+        Self { failover: level, ..self }
+    }
+
+    /// Level of calls giving up after all attempts failed, `Off` by default.
+    pub fn exhausted(self, level: LevelFilter) -> Self {
+        Self { exhausted: level, ..self }
+    }
+
+    /// Level of services becoming unhealthy or healthy again, `Warn` by default.
+    pub fn health(self, level: LevelFilter) -> Self {
+        Self { health: level, ..self }
+    }
+
+    /// Level of moves back to a service of a preferred tier, `Info` by default. Failed attempts
+    /// to move back are logged as failures.
+    pub fn failback(self, level: LevelFilter) -> Self {
+        Self { failback: level, ..self }
+    }
+
+    /// Level of failed or empty service discoveries, `Warn` by default.
+    pub fn discovery(self, level: LevelFilter) -> Self {
+        Self { discovery: level, ..self }
+    }
+
+    /// Log each kind of event at most once every `interval` per service, and exhausted calls and
+    /// discovery failures at most once every `interval`. The number of events suppressed meanwhile
+    /// is reported along with the next logged one. Disabled by default.
+    pub fn rate_limit(self, interval: Duration) -> Self {
+        Self { rate_limit: Some(interval), ..self }
+    }
+
+    fn enabled(level: LevelFilter) -> Option<Level> {
+        level.to_level().filter(|&level| log::log_enabled!(target: TARGET, level))
+    }
+
+    /// Log a failed attempt against the service at `index`, represented with `redact`.
+    pub(crate) fn log_failure<S, E: Display>(
+        &self,
+        index: usize,
+        src: &Source<S>,
+        redact: Redact<S>,
+        e: &E,
+    ) {
+        let level = match Self::enabled(self.failure) {
+            Some(level) => level,
+            None => return,
+        };
+        if let Some(suppressed) = src.failure_log.admit(self.rate_limit) {
+            log::log!(
+                target: TARGET,
+                level,
+                index = index,
+                service:% = Redacted::new(&src.src, redact),
+                error:% = e,
+                suppressed = suppressed;
+                "Service failed"
+            );
+        }
+    }
+
+    /// Log a move from the service at `from` to the one at `to`, represented with `redact`.
+    pub(crate) fn log_failover<S>(
+        &self,
+        from: (usize, &Source<S>),
+        to: (usize, &Source<S>),
+        redact: Redact<S>,
+    ) {
+        let level = match Self::enabled(self.failover) {
+            Some(level) => level,
+            None => return,
+        };
+        if let Some(suppressed) = from.1.failover_log.admit(self.rate_limit) {
+            log::log!(
+                target: TARGET,
+                level,
+                from = from.0,
+                to = to.0,
+                service:% = Redacted::new(&to.1.src, redact),
+                suppressed = suppressed;
+                "Failing over"
+            );
+        }
+    }
+
+    /// Log a call that gave up after `attempts` failed attempts, the last one with `error`.
+    pub(crate) fn log_exhausted<E: Display>(&self, limiter: &Limiter, attempts: usize, error: &E) {
+        let level = match Self::enabled(self.exhausted) {
+            Some(level) => level,
+            None => return,
+        };
+        if let Some(suppressed) = limiter.admit(self.rate_limit) {
+            log::log!(
+                target: TARGET,
+                level,
+                attempts = attempts,
+                error:% = error,
+                suppressed = suppressed;
+                "All attempts failed"
+            );
+        }
+    }
+
+    /// Log a change of health of the service at `index`, represented with `redact`.
+    pub(crate) fn log_health<S>(
+        &self,
+        index: usize,
+        src: &Source<S>,
+        redact: Redact<S>,
+        healthy: bool,
+    ) {
+        let level = match Self::enabled(self.health) {
+            Some(level) => level,
+            None => return,
+        };
+        if let Some(suppressed) = src.health_log.admit(self.rate_limit) {
+            let service = Redacted::new(&src.src, redact);
+            if healthy {
+                log::log!(
+                    target: TARGET,
+                    level,
+                    index = index,
+                    service:% = service,
+                    suppressed = suppressed;
+                    "Service healthy again"
+                );
+            } else {
+                log::log!(
+                    target: TARGET,
+                    level,
+                    index = index,
+                    service:% = service,
+                    suppressed = suppressed;
+                    "Service unhealthy"
+                );
+            }
+        }
+    }
+
+    /// Log a move back to the service at `index`, represented with `redact`.
+    pub(crate) fn log_failback<S>(&self, index: usize, src: &Source<S>, redact: Redact<S>) {
+        let level = match Self::enabled(self.failback) {
+            Some(level) => level,
+            None => return,
+        };
+        if let Some(suppressed) = src.failback_log.admit(self.rate_limit) {
+            log::log!(
+                target: TARGET,
+                level,
+                index = index,
+                service:% = Redacted::new(&src.src, redact),
+                suppressed = suppressed;
+                "Failing back"
+            );
+        }
+    }
+
+    /// Log a discovery that failed with `error`, or yielded no service if there is none.
+    pub(crate) fn log_discovery(&self, limiter: &Limiter, error: Option<&dyn Display>) {
+        let level = match Self::enabled(self.discovery) {
+            Some(level) => level,
+            None => return,
+        };
+        if let Some(suppressed) = limiter.admit(self.rate_limit) {
+            match error {
+                Some(error) => log::log!(
+                    target: TARGET,
+                    level,
+                    error:% = error,
+                    suppressed = suppressed;
+                    "Service discovery failed"
+                ),
+                None => log::log!(
+                    target: TARGET,
+                    level,
+                    suppressed = suppressed;
+                    "No service discovered, keeping the current ones"
+                ),
+            }
+        }
+    }
+}
+
+/// Rate limiter of a kind of event.
+#[derive(Debug, Default)]
+pub(crate) struct Limiter(Mutex<Option<(Instant, u64)>>);
+
+impl Limiter {
+    /// Account for an event. Returns the number of events suppressed since the last logged one,
+    /// or `None` if this one must be suppressed as well.
+    fn admit(&self, interval: Option<Duration>) -> Option<u64> {
+        let interval = match interval {
+            Some(interval) => interval,
+            None => return Some(0),
+        };
+        let mut state = self.0.lock().unwrap();
+        let now = Instant::now();
+
+        match &mut *state {
+            Some((since, suppressed)) if now < *since + interval => {
+                *suppressed += 1;
+                None
+            }
+            state => {
+                let suppressed = state.map_or(0, |(_, suppressed)| suppressed);
+                *state = Some((now, 0));
+                Some(suppressed)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Error, ErrorKind},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Mutex,
+        },
+        thread::{self, ThreadId},
+    };
+
+    use log::{kv::Key, Metadata, Record};
+
+    use super::*;
+    use crate::{async_trait, Connector, HealthCheck, RoundRobin};
+
+    /// Logger capturing the level, message and suppressed count of the records, along with the
+    /// thread logging them, as tests run concurrently
+    struct Capture(Mutex<Vec<(ThreadId, Level, String, String)>>);
+
+    impl log::Log for Capture {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record<'_>) {
+            let suppressed = record.key_values().get(Key::from("suppressed"));
+            self.0.lock().unwrap().push((
+                thread::current().id(),
+                record.level(),
+                record.args().to_string(),
+                suppressed.map(|v| v.to_string()).unwrap_or_default(),
+            ));
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: Capture = Capture(Mutex::new(Vec::new()));
+
+    /// Install the capturing logger, returning a function listing the records of this thread
+    fn capture() -> impl Fn() -> Vec<(Level, String, String)> {
+        let _ = log::set_logger(&LOGGER);
+        log::set_max_level(LevelFilter::Trace);
+
+        || {
+            let id = thread::current().id();
+            let records = LOGGER.0.lock().unwrap();
+            records.iter().filter(|r| r.0 == id).map(|r| (r.1, r.2.clone(), r.3.clone())).collect()
+        }
+    }
+
+    /// Connector that always fails
+    struct Conn;
+
+    #[async_trait]
+    impl Connector<u16, u16, Error> for Conn {
+        async fn connect(&self, _: &u16) -> Result<u16, Error> {
+            Err(ErrorKind::ConnectionRefused.into())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limit() {
+        let records = capture();
+        let logging = Logging::default()
+            .failover(LevelFilter::Info)
+            .exhausted(LevelFilter::Warn)
+            .rate_limit(Duration::from_secs(10));
+        let rr = RoundRobin::new(vec![0, 1], Conn).max_attempts(2).logging(logging);
+
+        let expected = |suppressed: &str| {
+            [
+                (Level::Info, "Failing over"),
+                (Level::Error, "Service failed"),
+                (Level::Info, "Failing over"),
+                (Level::Error, "Service failed"),
+                (Level::Warn, "All attempts failed"),
+            ]
+            .map(|(level, msg)| (level, msg.to_owned(), suppressed.to_owned()))
+        };
+
+        // Everything is logged once, then suppressed until the interval elapses
+        for _ in 0..3 {
+            rr.run(|n| async move { Ok(*n) }).await.unwrap_err();
+        }
+        let logged = records();
+        assert_eq!(logged, expected("0"));
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        rr.run(|n| async move { Ok(*n) }).await.unwrap_err();
+        let logged = records();
+        assert_eq!(logged[5..], expected("2"));
+    }
+
+    /// Health check reporting the services as unhealthy and healthy again, in turns
+    #[derive(Default)]
+    struct Flapping(AtomicUsize);
+
+    #[async_trait]
+    impl HealthCheck<u16> for Flapping {
+        async fn check(&self, _: &u16) -> bool {
+            self.0.fetch_add(1, Ordering::Relaxed) % 2 == 1
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limit_health() {
+        let records = capture();
+        let rr = RoundRobin::new(vec![0], Conn)
+            .health_check(Flapping::default(), Duration::from_secs(1));
+
+        // The logging configuration also applies to the already running health check
+        let logging =
+            Logging::default().health(LevelFilter::Info).rate_limit(Duration::from_secs(10));
+        let rr = rr.logging(logging);
+
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert_eq!(records(), [(Level::Info, "Service unhealthy".into(), "0".into())]);
+
+        // Changes are suppressed until the interval elapses
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert_eq!(records()[1..], [(Level::Info, "Service unhealthy".into(), "9".into())]);
+        drop(rr);
+    }
+}
@@ -0,0 +1,157 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use arc_swap::ArcSwapOption;
+use tokio::{
+    sync::{Mutex, MutexGuard},
+    time::Instant,
+};
+
+use crate::{sources::Source, Classification, Failure, RunError};
+
+/// A connected service, along with the source it is connected to.
+pub(crate) struct Connection<Svc, SvcSrc> {
+    pub(crate) svc: Arc<Svc>,
+    pub(crate) source: Arc<Source<SvcSrc>>,
+    pub(crate) since: Instant,
+}
+
+impl<Svc, SvcSrc> Connection<Svc, SvcSrc> {
+    pub(crate) fn new(svc: Svc, source: &Arc<Source<SvcSrc>>) -> Self {
+        Self { svc: Arc::new(svc), source: source.clone(), since: Instant::now() }
+    }
+}
+
+/// Failure of a connection that did not move on to the next service, shared with the callers
+/// that waited for it.
+struct ConnectFailure<E> {
+    index: usize,
+    class: Classification,
+    error: Arc<E>,
+}
+
+/// Connection to a service, established once for all concurrent callers.
+pub(crate) struct Slot<Svc, SvcSrc, E> {
+    /// Already connected service handler. We use Arc here to be able to easily clone the service
+    /// handler and avoid issues with references, as they don't play nicely with futures. It is
+    /// atomically swapped, so that calls against an established connection take no lock.
+    service: ArcSwapOption<Connection<Svc, SvcSrc>>,
+
+    /// Held while connecting, so that a single connection is established at a time. Holds the
+    /// failure of the last connection, if any, for the callers that waited for it.
+    connecting: Mutex<Option<ConnectFailure<E>>>,
+
+    /// Number of connections made, telling waiting callers wether one was made in the meantime.
+    connects: AtomicUsize,
+}
+
+/// Connected service, or how to get one, see [`Slot::acquire`].
+pub(crate) enum Acquired<'a, Svc, SvcSrc, E> {
+    /// The service is connected, possibly by a concurrent caller.
+    Connected(Arc<Connection<Svc, SvcSrc>>),
+    /// The connection made by a concurrent caller failed without moving on.
+    Failed(Failure<E>),
+    /// The service is not connected, and is to be connected to by the caller.
+    Connect(Connecting<'a, Svc, SvcSrc, E>),
+}
+
+/// Connection in progress, holding the connecting lock until dropped.
+pub(crate) struct Connecting<'a, Svc, SvcSrc, E> {
+    slot: &'a Slot<Svc, SvcSrc, E>,
+    failed: MutexGuard<'a, Option<ConnectFailure<E>>>,
+}
+
+impl<Svc, SvcSrc, E> Slot<Svc, SvcSrc, E> {
+    pub(crate) fn new() -> Self {
+        Self {
+            service: ArcSwapOption::empty(),
+            connecting: Mutex::new(None),
+            connects: AtomicUsize::new(0),
+        }
+    }
+
+    /// The connected service, if any.
+    pub(crate) fn load(&self) -> Option<Arc<Connection<Svc, SvcSrc>>> {
+        self.service.load_full()
+    }
+
+    /// Check wether `conn` is the connected service.
+    pub(crate) fn holds(&self, conn: &Arc<Connection<Svc, SvcSrc>>) -> bool {
+        self.service.load().as_ref().is_some_and(|c| Arc::ptr_eq(c, conn))
+    }
+
+    /// Replace the connected service. Must be called with the connecting lock held.
+    pub(crate) fn store(&self, conn: Arc<Connection<Svc, SvcSrc>>) {
+        self.service.store(Some(conn));
+    }
+
+    /// Drop the connection to the service, unless it was already replaced. Returns wether it
+    /// dropped it.
+    pub(crate) fn disconnect(&self, conn: &Arc<Connection<Svc, SvcSrc>>) -> bool {
+        let prev = self.service.compare_and_swap(&Some(conn.clone()), None);
+        prev.as_ref().is_some_and(|c| Arc::ptr_eq(c, conn))
+    }
+
+    /// Take the connecting lock.
+    pub(crate) async fn lock(&self) -> Connecting<'_, Svc, SvcSrc, E> {
+        Connecting { slot: self, failed: self.connecting.lock().await }
+    }
+
+    /// The connected service, or how to get one. Concurrent callers wait for the connection in
+    /// progress, if any, rather than connecting on their own, and fail along with it should it
+    /// have failed without moving on.
+    pub(crate) async fn acquire(&self) -> Acquired<'_, Svc, SvcSrc, E> {
+        if let Some(conn) = self.load() {
+            return Acquired::Connected(conn);
+        }
+
+        let connects = self.connects.load(Ordering::Relaxed);
+        let connecting = self.lock().await;
+        if let Some(conn) = self.load() {
+            return Acquired::Connected(conn);
+        }
+        let since = self.connects.load(Ordering::Relaxed) != connects;
+        match connecting.failed.as_ref().filter(|_| since) {
+            Some(f) => Acquired::Failed(Failure {
+                error: RunError::Connect(f.error.clone()),
+                class: f.class,
+                skipped: false,
+                index: f.index,
+            }),
+            None => Acquired::Connect(connecting),
+        }
+    }
+}
+
+impl<Svc, SvcSrc, E> Connecting<'_, Svc, SvcSrc, E> {
+    /// Record the outcome of the connection to `source`, at `index` in the source list. Should it
+    /// fail without moving on to the next service, its error is shared by the caller and the
+    /// waiting ones alike.
+    pub(crate) fn complete(
+        &mut self,
+        index: usize,
+        source: &Arc<Source<SvcSrc>>,
+        res: Result<Svc, Failure<E>>,
+    ) -> Result<Arc<Connection<Svc, SvcSrc>>, Failure<E>> {
+        self.slot.connects.fetch_add(1, Ordering::Relaxed);
+        *self.failed = None;
+        match res {
+            Ok(svc) => {
+                let conn = Arc::new(Connection::new(svc, source));
+                self.slot.store(conn.clone());
+                Ok(conn)
+            }
+            Err(f) => Err(match f.error {
+                RunError::Service(e) if !f.is_next() => {
+                    let error = Arc::new(e);
+                    let class = f.class;
+                    *self.failed = Some(ConnectFailure { index, class, error: error.clone() });
+                    Failure { error: RunError::Connect(error), index, ..f }
+                }
+                _ => f.at(index),
+            }),
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! Shared list of service sources, that can be changed at runtime.
+
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use arc_swap::ArcSwap;
+use tokio::sync::Notify;
+
+use crate::{
+    breaker::{Breaker, CircuitBreaker},
+    logging::{Limiter, Logging},
+    redact::{redact_passwords, Redact},
+    stats::Counters,
+    Error,
+};
+
+/// A service source, along with the state of the service.
+pub(crate) struct Source<SvcSrc> {
+    pub(crate) src: SvcSrc,
+
+    /// Priority tier of the source, the lower the more preferred.
+    pub(crate) tier: usize,
+
+    /// Circuit state of the service.
+    pub(crate) breaker: Breaker,
+
+    /// Health of the service, as reported by the health check task. Healthy if there is none.
+    pub(crate) healthy: AtomicBool,
+
+    /// The source was removed from the list. Connections to it must be dropped.
+    pub(crate) removed: AtomicBool,
+
+    /// Statistics of the service.
+    pub(crate) stats: Counters,
+
+    /// Rate limiters of the failures, failovers, health changes and failbacks logged for the
+    /// service.
+    pub(crate) failure_log: Limiter,
+    pub(crate) failover_log: Limiter,
+    pub(crate) health_log: Limiter,
+    pub(crate) failback_log: Limiter,
+}
+
+impl<SvcSrc> Source<SvcSrc> {
+    pub(crate) fn new(src: SvcSrc) -> Arc<Self> {
+        Self::in_tier(src, 0)
+    }
+
+    pub(crate) fn in_tier(src: SvcSrc, tier: usize) -> Arc<Self> {
+        Arc::new(Self {
+            src,
+            tier,
+            breaker: Breaker::default(),
+            healthy: AtomicBool::new(true),
+            removed: AtomicBool::new(false),
+            stats: Counters::default(),
+            failure_log: Limiter::default(),
+            failover_log: Limiter::default(),
+            health_log: Limiter::default(),
+            failback_log: Limiter::default(),
+        })
+    }
+
+    /// Check wether the service may be connected to, given the circuit breaker configuration.
+    pub(crate) fn admit(&self, breaker: Option<&CircuitBreaker>) -> Result<(), Error> {
+        if !self.healthy.load(Ordering::Relaxed) {
+            return Err(Error::Unhealthy);
+        }
+        match breaker {
+            Some(config) if !self.breaker.acquire(config) => Err(Error::CircuitOpen),
+            _ => Ok(()),
+        }
+    }
+
+    /// Report the outcome of an attempt to the circuit breaker of the service, `failed` being
+    /// wether it failed with a next error.
+    pub(crate) fn report(&self, breaker: Option<&CircuitBreaker>, failed: bool) {
+        if let Some(config) = breaker {
+            if failed {
+                self.breaker.failure(config);
+            } else {
+                self.breaker.success();
+            }
+        }
+    }
+}
+
+/// Immutable snapshot of the source list.
+pub(crate) type Snapshot<SvcSrc> = Arc<Vec<Arc<Source<SvcSrc>>>>;
+
+/// List of sources, ordered by tier, atomically replaced on every change, so that reading it takes
+/// no lock.
+pub(crate) struct Sources<SvcSrc> {
+    list: ArcSwap<Vec<Arc<Source<SvcSrc>>>>,
+
+    /// Held while updating the list, so that concurrent updates are not lost.
+    updating: Mutex<()>,
+
+    /// Current service source being connected, as an ever increasing index in the list.
+    pub(crate) current: AtomicUsize,
+
+    /// Notified when all services failed, for the source provider to refresh the list.
+    pub(crate) refresh: Notify,
+
+    /// Notified when the list changed, for calls waiting for sources.
+    pub(crate) changed: Notify,
+
+    /// Logging configuration and representation of the sources, for the background tasks.
+    logging: Mutex<(Logging, Redact<SvcSrc>)>,
+
+    /// Rate limiter of the logged discovery failures.
+    pub(crate) discovery_log: Limiter,
+}
+
+impl<SvcSrc> Sources<SvcSrc> {
+    pub(crate) fn new(sources: Vec<SvcSrc>) -> Self
+    where
+        SvcSrc: Debug,
+    {
+        Self::tiered(vec![sources])
+    }
+
+    pub(crate) fn tiered(tiers: Vec<Vec<SvcSrc>>) -> Self
+    where
+        SvcSrc: Debug,
+    {
+        let list = tiers
+            .into_iter()
+            .enumerate()
+            .flat_map(|(tier, srcs)| srcs.into_iter().map(move |src| Source::in_tier(src, tier)))
+            .collect();
+        Self {
+            list: ArcSwap::from_pointee(list),
+            updating: Mutex::new(()),
+            current: AtomicUsize::new(0),
+            refresh: Notify::new(),
+            changed: Notify::new(),
+            logging: Mutex::new((Logging::default(), redact_passwords)),
+            discovery_log: Limiter::default(),
+        }
+    }
+
+    /// Logging configuration and representation of the sources, as set on the round-robin.
+    pub(crate) fn logging(&self) -> (Logging, Redact<SvcSrc>) {
+        *self.logging.lock().unwrap()
+    }
+
+    pub(crate) fn set_logging(&self, logging: Logging, redact: Redact<SvcSrc>) {
+        *self.logging.lock().unwrap() = (logging, redact);
+    }
+
+    pub(crate) fn snapshot(&self) -> Snapshot<SvcSrc> {
+        self.list.load_full()
+    }
+
+    /// Replace the list with the one built by `update` from the current one, then sorted by tier.
+    ///
+    /// The `current` index is moved along so that it still points to the same source. Should it
+    /// have been removed, it points to the first following source that was kept.
+    pub(crate) fn update<F>(&self, update: F)
+    where
+        F: FnOnce(&[Arc<Source<SvcSrc>>]) -> Vec<Arc<Source<SvcSrc>>>,
+    {
+        let _updating = self.updating.lock().unwrap();
+        let old = self.list.load_full();
+        let mut new = update(&old);
+        new.sort_by_key(|s| s.tier);
+
+        for source in old.iter() {
+            if !new.iter().any(|s| Arc::ptr_eq(s, source)) {
+                source.removed.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let n_old = old.len().max(1);
+        let index = self.current.load(Ordering::Relaxed) % n_old;
+        let index = (0..old.len())
+            .map(|i| &old[(index + i) % n_old])
+            .find_map(|source| new.iter().position(|s| Arc::ptr_eq(s, source)))
+            .unwrap_or(0);
+        self.current.store(index, Ordering::Relaxed);
+
+        self.list.store(Arc::new(new));
+        self.changed.notify_waiters();
+    }
+
+    /// Replace the list with `sources`. Sources present in both lists keep their state and tier,
+    /// new ones are put in the first tier.
+    pub(crate) fn replace(&self, sources: Vec<SvcSrc>)
+    where
+        SvcSrc: PartialEq,
+    {
+        self.update(|old| {
+            sources
+                .into_iter()
+                .map(|src| match old.iter().find(|s| s.src == src) {
+                    Some(source) => source.clone(),
+                    None => Source::new(src),
+                })
+                .collect()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn srcs(sources: &Sources<i32>) -> Vec<i32> {
+        sources.snapshot().iter().map(|s| s.src).collect()
+    }
+
+    #[test]
+    fn test_update() {
+        let sources = Sources::new(vec![0, 1, 2, 3]);
+        let current = &sources.current;
+        current.store(6, Ordering::Relaxed);
+        let removed = sources.snapshot()[2].clone();
+
+        // Removing the current source moves to the next one
+        sources.update(|s| s.iter().filter(|s| s.src != 2).cloned().collect());
+        assert_eq!(srcs(&sources), [0, 1, 3]);
+        assert_eq!(current.load(Ordering::Relaxed), 2);
+        assert!(removed.removed.load(Ordering::Relaxed));
+
+        // Removing a previous source keeps the current one
+        sources.update(|s| s[1..].to_vec());
+        assert_eq!(srcs(&sources), [1, 3]);
+        assert_eq!(current.load(Ordering::Relaxed), 1);
+
+        // Removing the last one wraps around
+        sources.update(|s| s[..1].to_vec());
+        assert_eq!(srcs(&sources), [1]);
+        assert_eq!(current.load(Ordering::Relaxed), 0);
+
+        // Nothing is kept
+        sources.update(|_| vec![Source::new(4), Source::new(5)]);
+        assert_eq!(srcs(&sources), [4, 5]);
+        assert_eq!(current.load(Ordering::Relaxed), 0);
+
+        // Sources are kept ordered by tier
+        sources
+            .update(|s| s.iter().cloned().chain([Source::in_tier(6, 1), Source::new(7)]).collect());
+        assert_eq!(srcs(&sources), [4, 5, 7, 6]);
+    }
+}
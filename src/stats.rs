@@ -0,0 +1,198 @@
+//! Per-service statistics.
+//!
+//! Every service of a [`RoundRobin`](crate::RoundRobin) keeps counters of its connections, calls
+//! and failovers, along with latency summaries. They are cheap to snapshot with
+//! [`RoundRobin::stats`](crate::RoundRobin::stats), e.g. from a metrics scrape. With the `serde`
+//! feature, snapshots can be serialized.
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+/// Summary of the latencies of an operation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Latency {
+    /// Number of operations.
+    pub count: u64,
+    /// Sum of the latencies of all operations.
+    pub total: Duration,
+    /// Lowest latency.
+    pub min: Duration,
+    /// Highest latency.
+    pub max: Duration,
+}
+
+impl Latency {
+    /// Mean latency, zero if there was no operation.
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            count => self.total / u32::try_from(count).unwrap_or(u32::MAX),
+        }
+    }
+}
+
+/// Snapshot of the statistics of a service, as returned by
+/// [`RoundRobin::stats`](crate::RoundRobin::stats).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct SourceStats {
+    /// Index of the service in the source list.
+    pub index: usize,
+    /// Representation of the service's source, see
+    /// [`RoundRobin::redact`](crate::RoundRobin::redact).
+    pub source: String,
+    /// Number of connection attempts.
+    pub connects: u64,
+    /// Number of failed connection attempts.
+    pub connect_failures: u64,
+    /// Number of successful calls.
+    pub successes: u64,
+    /// Number of failed calls.
+    pub failures: u64,
+    /// Number of times the service was moved on from, be it because it failed or was skipped.
+    pub failovers: u64,
+    /// Number of connection attempts and calls that failed since the last success.
+    pub consecutive_failures: u64,
+    /// Latencies of the connection attempts.
+    pub connect_latency: Latency,
+    /// Latencies of the calls.
+    pub run_latency: Latency,
+    /// Time of the last successful call.
+    pub last_success: Option<SystemTime>,
+    /// Time of the last failed connection attempt or call.
+    pub last_failure: Option<SystemTime>,
+}
+
+/// Latencies of an operation, in nanoseconds.
+#[derive(Debug)]
+struct Latencies {
+    count: AtomicU64,
+    total: AtomicU64,
+    min: AtomicU64,
+    max: AtomicU64,
+}
+
+impl Default for Latencies {
+    fn default() -> Self {
+        Self {
+            count: AtomicU64::new(0),
+            total: AtomicU64::new(0),
+            min: AtomicU64::new(u64::MAX),
+            max: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Latencies {
+    fn record(&self, elapsed: Duration) {
+        let nanos = nanos(elapsed);
+        self.min.fetch_min(nanos, Ordering::Relaxed);
+        self.max.fetch_max(nanos, Ordering::Relaxed);
+        let _ = self.total.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |total| {
+            Some(total.saturating_add(nanos))
+        });
+        // Counted last, so that the latency of a counted operation is always part of the summary
+        self.count.fetch_add(1, Ordering::Release);
+    }
+
+    fn snapshot(&self) -> Latency {
+        match self.count.load(Ordering::Acquire) {
+            0 => Latency::default(),
+            count => Latency {
+                count,
+                total: Duration::from_nanos(self.total.load(Ordering::Relaxed)),
+                min: Duration::from_nanos(self.min.load(Ordering::Relaxed)),
+                max: Duration::from_nanos(self.max.load(Ordering::Relaxed)),
+            },
+        }
+    }
+}
+
+/// Statistics of a single service, updated without locking. A snapshot may be taken in the middle
+/// of an update, its counters then being off by one operation.
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    connects: AtomicU64,
+    connect_failures: AtomicU64,
+    successes: AtomicU64,
+    failures: AtomicU64,
+    failovers: AtomicU64,
+    consecutive_failures: AtomicU64,
+    connect_latency: Latencies,
+    run_latency: Latencies,
+    /// Times of the last success and failure, in nanoseconds since the Unix epoch, zero if none.
+    last_success: AtomicU64,
+    last_failure: AtomicU64,
+}
+
+impl Counters {
+    /// Record a connection attempt that took `elapsed`.
+    pub(crate) fn connected(&self, elapsed: Duration, failed: bool) {
+        self.connects.fetch_add(1, Ordering::Relaxed);
+        self.connect_latency.record(elapsed);
+        if failed {
+            self.connect_failures.fetch_add(1, Ordering::Relaxed);
+            self.failed();
+        }
+    }
+
+    /// Record a call that took `elapsed`.
+    pub(crate) fn ran(&self, elapsed: Duration, failed: bool) {
+        self.run_latency.record(elapsed);
+        if failed {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+            self.failed();
+        } else {
+            self.successes.fetch_add(1, Ordering::Relaxed);
+            self.consecutive_failures.store(0, Ordering::Relaxed);
+            self.last_success.store(now(), Ordering::Relaxed);
+        }
+    }
+
+    fn failed(&self) {
+        self.consecutive_failures.fetch_add(1, Ordering::Relaxed);
+        self.last_failure.store(now(), Ordering::Relaxed);
+    }
+
+    /// Record moving on from the service.
+    pub(crate) fn failed_over(&self) {
+        self.failovers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self, index: usize, source: String) -> SourceStats {
+        let time = |nanos: &AtomicU64| match nanos.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(UNIX_EPOCH + Duration::from_nanos(nanos)),
+        };
+        SourceStats {
+            index,
+            source,
+            connects: self.connects.load(Ordering::Relaxed),
+            connect_failures: self.connect_failures.load(Ordering::Relaxed),
+            successes: self.successes.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            failovers: self.failovers.load(Ordering::Relaxed),
+            consecutive_failures: self.consecutive_failures.load(Ordering::Relaxed),
+            connect_latency: self.connect_latency.snapshot(),
+            run_latency: self.run_latency.snapshot(),
+            last_success: time(&self.last_success),
+            last_failure: time(&self.last_failure),
+        }
+    }
+}
+
+/// Duration in nanoseconds, saturating.
+fn nanos(duration: Duration) -> u64 {
+    duration.as_nanos().try_into().unwrap_or(u64::MAX)
+}
+
+/// Current time, in nanoseconds since the Unix epoch. Never zero, which stands for no time.
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, nanos).max(1)
+}
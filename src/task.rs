@@ -0,0 +1,22 @@
+use std::future::Future;
+
+use tokio::task::JoinHandle;
+
+/// Background task tied to the lifetime of a round-robin: it is stopped when dropped.
+pub(crate) struct Task(JoinHandle<()>);
+
+impl Task {
+    /// Spawn the task on the current Tokio runtime.
+    pub(crate) fn spawn<F>(fut: F) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        Self(tokio::spawn(fut))
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
@@ -0,0 +1,86 @@
+//! Metrics recorded through the [`metrics`] facade, see
+//! [`RoundRobin::set_name`](crate::RoundRobin::set_name).
+
+use std::time::Duration;
+
+use metrics::{counter, histogram};
+
+pub(crate) fn attempt(name: &str, index: usize, ok: bool) {
+    let result = if ok { "ok" } else { "error" };
+    let labels =
+        [("name", name.to_owned()), ("index", index.to_string()), ("result", result.into())];
+    counter!("tourniquet_attempts_total", &labels).increment(1);
+}
+
+pub(crate) fn failover(name: &str, from: usize) {
+    let labels = [("name", name.to_owned()), ("index", from.to_string())];
+    counter!("tourniquet_failovers_total", &labels).increment(1);
+}
+
+pub(crate) fn connect(name: &str, index: usize, elapsed: Duration) {
+    let labels = [("name", name.to_owned()), ("index", index.to_string())];
+    histogram!("tourniquet_connect_duration_seconds", &labels).record(elapsed);
+}
+
+pub(crate) fn run(name: &str, index: usize, elapsed: Duration) {
+    let labels = [("name", name.to_owned()), ("index", index.to_string())];
+    histogram!("tourniquet_run_duration_seconds", &labels).record(elapsed);
+}
+
+pub(crate) fn exhausted(name: &str) {
+    counter!("tourniquet_exhausted_total", "name" => name.to_owned()).increment(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+    use crate::{testing::Conn, RoundRobin};
+
+    #[test]
+    fn test_metrics() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let rt = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+
+        metrics::with_local_recorder(&recorder, || {
+            rt.block_on(async {
+                let rr = RoundRobin::new(vec![0, 1], Conn).name("db").max_attempts(1);
+                rr.run(|n| async move { Ok(*n) }).await.unwrap_err();
+                rr.run(|n| async move { Ok(*n) }).await.unwrap();
+            })
+        });
+
+        let metrics: HashMap<_, _> = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| {
+                let labels: Vec<_> =
+                    key.key().labels().map(|l| format!("{}={}", l.key(), l.value())).collect();
+                (format!("{}{{{}}}", key.key().name(), labels.join(",")), value)
+            })
+            .collect();
+        let histogram_len = |key: &str| match &metrics[key] {
+            DebugValue::Histogram(values) => values.len(),
+            value => panic!("{} is not a histogram: {:?}", key, value),
+        };
+
+        let attempts = "tourniquet_attempts_total";
+        assert_eq!(
+            metrics[&format!("{}{{name=db,index=0,result=error}}", attempts)],
+            DebugValue::Counter(1)
+        );
+        assert_eq!(
+            metrics[&format!("{}{{name=db,index=1,result=ok}}", attempts)],
+            DebugValue::Counter(1)
+        );
+        assert_eq!(metrics["tourniquet_failovers_total{name=db,index=0}"], DebugValue::Counter(1));
+        assert_eq!(metrics["tourniquet_exhausted_total{name=db}"], DebugValue::Counter(1));
+        assert_eq!(histogram_len("tourniquet_connect_duration_seconds{name=db,index=0}"), 1);
+        assert_eq!(histogram_len("tourniquet_connect_duration_seconds{name=db,index=1}"), 1);
+        assert_eq!(histogram_len("tourniquet_run_duration_seconds{name=db,index=1}"), 1);
+    }
+}
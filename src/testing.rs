@@ -0,0 +1,18 @@
+//! Fixtures shared by the tests of the telemetry modules.
+
+use std::io::{Error, ErrorKind};
+
+use crate::{async_trait, Connector};
+
+/// Connector failing for the first source
+pub(crate) struct Conn;
+
+#[async_trait]
+impl Connector<u16, u16, Error> for Conn {
+    async fn connect(&self, src: &u16) -> Result<u16, Error> {
+        match src {
+            0 => Err(ErrorKind::ConnectionRefused.into()),
+            src => Ok(*src),
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Attempt-level tracing, see the [crate documentation](crate#tracing).
+
+use std::fmt::Display;
+
+use tracing::{
+    field::{display, Empty},
+    Span,
+};
+
+use crate::{Classification, Failure};
+
+/// Span of a single attempt.
+#[cfg(not(feature = "otel-semconv"))]
+pub(crate) fn attempt(number: usize) -> Span {
+    tracing::info_span!(
+        "attempt",
+        attempt = number,
+        index = Empty,
+        service = Empty,
+        reconnect = false,
+        outcome = Empty,
+        error = Empty,
+    )
+}
+
+/// Span of a single attempt, along with the OpenTelemetry semantic-convention fields.
+#[cfg(feature = "otel-semconv")]
+pub(crate) fn attempt(number: usize) -> Span {
+    tracing::info_span!(
+        "attempt",
+        attempt = number,
+        index = Empty,
+        service = Empty,
+        reconnect = false,
+        outcome = Empty,
+        error = Empty,
+        otel.status_code = Empty,
+        otel.status_description = Empty,
+        error.type = Empty,
+    )
+}
+
+/// Record the outcome of the attempt on its span.
+pub(crate) fn record<T, E: Display>(span: &Span, res: &Result<T, Failure<E>>) {
+    let outcome = match res {
+        Ok(_) => "ok",
+        Err(Failure { skipped: true, .. }) => "skipped",
+        Err(Failure { class: Classification::Fatal, .. }) => "fatal",
+        Err(Failure { class: Classification::Retry(_), .. }) => "retry",
+        Err(Failure { class: Classification::Reconnect(_), .. }) => "reconnect",
+        Err(Failure { class: Classification::Next(_), .. }) => "next",
+    };
+    span.record("outcome", outcome);
+    if let Err(f) = res {
+        span.record("error", display(&f.error));
+    }
+
+    #[cfg(feature = "otel-semconv")]
+    match res {
+        Ok(_) => {
+            span.record("otel.status_code", "OK");
+        }
+        Err(f) => {
+            span.record("otel.status_code", "ERROR");
+            span.record("otel.status_description", display(&f.error));
+            span.record("error.type", outcome);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        fmt::Debug,
+        sync::{Arc, Mutex},
+    };
+
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        Event, Subscriber,
+    };
+    use tracing_subscriber::{layer::Context, prelude::*, registry::LookupSpan, Layer};
+
+    use crate::{testing::Conn, RoundRobin};
+
+    /// Fields of a span, along with the messages of its events
+    #[derive(Debug, Default)]
+    struct Recorded {
+        fields: HashMap<String, String>,
+        events: Vec<String>,
+    }
+
+    impl Visit for Recorded {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.fields.insert(field.name().into(), format!("{:?}", value));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.fields.insert(field.name().into(), value.into());
+        }
+    }
+
+    /// Layer recording the attempt spans, in order
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<(Id, Recorded)>>>);
+
+    impl Recorder {
+        fn with_span(&self, id: &Id, f: impl FnOnce(&mut Recorded)) {
+            let mut spans = self.0.lock().unwrap();
+            if let Some((_, recorded)) = spans.iter_mut().find(|(i, _)| i == id) {
+                f(recorded);
+            }
+        }
+    }
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Recorder {
+        fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, _: Context<'_, S>) {
+            if attrs.metadata().name() == "attempt" {
+                let mut recorded = Recorded::default();
+                attrs.record(&mut recorded);
+                self.0.lock().unwrap().push((id.clone(), recorded));
+            }
+        }
+
+        fn on_record(&self, id: &Id, values: &Record<'_>, _: Context<'_, S>) {
+            self.with_span(id, |recorded| values.record(recorded));
+        }
+
+        fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+            if let Some(span) = ctx.event_span(event) {
+                let mut fields = Recorded::default();
+                event.record(&mut fields);
+                let event = format!(
+                    "{} {} -> {}",
+                    fields.fields["message"], fields.fields["from"], fields.fields["to"]
+                );
+                self.with_span(&span.id(), |recorded| recorded.events.push(event));
+            }
+        }
+    }
+
+    #[test]
+    fn test_attempt_spans() {
+        let recorder = Recorder::default();
+        let subscriber = tracing_subscriber::registry().with(recorder.clone());
+        let rt = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+
+        tracing::subscriber::with_default(subscriber, || {
+            rt.block_on(async {
+                let rr = RoundRobin::new(vec![0, 1, 2], Conn);
+                assert_eq!(rr.run(|n| async move { Ok(*n) }).await.unwrap(), 1);
+                assert_eq!(rr.run(|n| async move { Ok(*n) }).await.unwrap(), 1);
+            })
+        });
+
+        let spans = recorder.0.lock().unwrap();
+        let field = |i: usize, name: &str| spans[i].1.fields.get(name).cloned().unwrap_or_default();
+        assert_eq!(spans.len(), 3);
+
+        // The first attempt connects and fails over
+        assert_eq!(
+            (field(0, "attempt"), field(0, "index"), field(0, "service")),
+            ("1".into(), "0".into(), "0".into())
+        );
+        assert_eq!((field(0, "reconnect"), field(0, "outcome")), ("true".into(), "next".into()));
+        assert_eq!(field(0, "error"), "connection refused");
+        assert_eq!(spans[0].1.events, ["failover 0 -> 1"]);
+
+        assert_eq!((field(1, "attempt"), field(1, "index")), ("2".into(), "1".into()));
+        assert_eq!((field(1, "reconnect"), field(1, "outcome")), ("true".into(), "ok".into()));
+        assert!(spans[1].1.events.is_empty());
+
+        // The next call reuses the connection
+        assert_eq!((field(2, "attempt"), field(2, "index")), ("1".into(), "1".into()));
+        assert_eq!((field(2, "reconnect"), field(2, "outcome")), ("false".into(), "ok".into()));
+
+        #[cfg(feature = "otel-semconv")]
+        {
+            assert_eq!(field(0, "otel.status_code"), "ERROR");
+            assert_eq!(field(0, "error.type"), "next");
+            assert_eq!(field(1, "otel.status_code"), "OK");
+        }
+    }
+}
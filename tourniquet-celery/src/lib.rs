@@ -39,6 +39,8 @@ use celery::{
     task::{AsyncResult, Signature, Task},
     Celery, CeleryBuilder,
 };
+#[cfg(feature = "trace")]
+use tourniquet::{redact_passwords, Redacted};
 use tourniquet::{Connector, Next, RoundRobin};
 #[cfg(feature = "trace")]
 use tracing::{
@@ -73,6 +75,14 @@ impl From<CeleryError> for RRCeleryError {
     }
 }
 
+/// Tourniquet errors are reported as IO errors, as celery has no better suited error. The original
+/// error is kept as the IO error's inner error.
+impl From<tourniquet::Error> for RRCeleryError {
+    fn from(e: tourniquet::Error) -> Self {
+        Self(IoError(e.into()))
+    }
+}
+
 impl From<RRCeleryError> for CeleryError {
     fn from(e: RRCeleryError) -> Self {
         e.0
@@ -121,7 +131,10 @@ impl<'a> Default for CeleryConnector<'a> {
 
 #[async_trait]
 impl<'a> Connector<String, Celery, RRCeleryError> for CeleryConnector<'a> {
-    #[cfg_attr(feature = "trace", tracing::instrument(skip(self), err))]
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(skip(self, url), fields(url = %Redacted::new(url, redact_passwords)), err),
+    )]
     async fn connect(&self, url: &String) -> Result<Celery, RRCeleryError> {
         let mut builder = CeleryBuilder::new(self.name, url.as_ref());
 
@@ -35,13 +35,16 @@ use tonic::{
     transport::{Channel, Endpoint, Uri},
     Status,
 };
+#[cfg(feature = "trace")]
+use tourniquet::{redact_passwords, Redacted};
 use tourniquet::{Connector, Next, RoundRobin};
 
-/// Wrapper for Tonic's errors (`Status` and transport `Error`)
+/// Wrapper for Tonic's errors (`Status` and transport `Error`), and tourniquet's own errors
 #[derive(Debug)]
 pub enum Error {
     Status(Status),
     Transport(tonic::transport::Error),
+    Tourniquet(tourniquet::Error),
 }
 
 impl Next for Error {
@@ -50,6 +53,7 @@ impl Next for Error {
 
         match self {
             Self::Transport(_) => true,
+            Self::Tourniquet(e) => e.is_next(),
             Self::Status(s) => matches!(
                 s.code(),
                 Cancelled | Unknown | DeadlineExceeded | Aborted | Internal | Unavailable,
@@ -70,11 +74,18 @@ impl From<tonic::transport::Error> for Error {
     }
 }
 
+impl From<tourniquet::Error> for Error {
+    fn from(e: tourniquet::Error) -> Self {
+        Self::Tourniquet(e)
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
         match self {
             Self::Status(s) => Display::fmt(s, f),
             Self::Transport(e) => Display::fmt(e, f),
+            Self::Tourniquet(e) => Display::fmt(e, f),
         }
     }
 }
@@ -84,6 +95,7 @@ impl StdError for Error {
         Some(match self {
             Self::Status(s) => s,
             Self::Transport(e) => e,
+            Self::Tourniquet(e) => e,
         })
     }
 }
@@ -106,7 +118,10 @@ impl TonicConnector {
 
 #[async_trait]
 impl Connector<Uri, Channel, Error> for TonicConnector {
-    #[cfg_attr(feature = "trace", tracing::instrument(skip(self), err))]
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(skip(self, uri), fields(uri = %Redacted::new(uri, redact_passwords)), err),
+    )]
     async fn connect(&self, uri: &Uri) -> Result<Channel, Error> {
         let mut ep = Channel::builder(uri.to_owned());
         if let Some(conf) = self.conf {
@@ -119,7 +134,10 @@ impl Connector<Uri, Channel, Error> for TonicConnector {
 
 #[async_trait]
 impl Connector<&'static str, Channel, Error> for TonicConnector {
-    #[cfg_attr(feature = "trace", tracing::instrument(skip(self), err))]
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(skip(self, uri), fields(uri = %Redacted::new(uri, redact_passwords)), err),
+    )]
     async fn connect(&self, uri: &&'static str) -> Result<Channel, Error> {
         let mut ep = Channel::from_static(uri);
         if let Some(conf) = self.conf {